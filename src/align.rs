@@ -0,0 +1,196 @@
+use std::time::Instant;
+
+use log::trace;
+
+use crate::{
+    transcode,
+    transcript::{self, Utterance},
+    Model, ModelError, TranscribeOptions,
+};
+
+impl Model {
+    /// Best-effort alignment of a known transcript to audio, given the audio is a byte
+    /// array of a file. Returns the timing of every word of `text`.
+    ///
+    /// whisper.cpp can't do true forced alignment. Instead, the audio is transcribed
+    /// with word timestamps, using `text` as the initial prompt to steer whisper towards
+    /// the same wording, and the words of `text` are matched to the recognised words.
+    /// Words that couldn't be matched are spread evenly between their matched
+    /// neighbours and have no [`Utterance::probability`].
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to align. An array of bytes.
+    /// - `text`: Known transcript of the audio.
+    /// - `options`: [`TranscribeOptions`] to transcribe with. `word_timestamps` is
+    ///   always enabled, and `initial_prompt` defaults to `text`.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// One [`Utterance`] per whitespace-separated word of `text`.
+    pub fn align(
+        &self,
+        audio: impl AsRef<[u8]>,
+        text: &str,
+        options: &TranscribeOptions,
+    ) -> Result<Vec<Utterance>, ModelError> {
+        trace!("Decoding audio.");
+        let decode_start = Instant::now();
+        let samples = transcode::decode(audio.as_ref().to_vec())?.samples;
+        trace!("Decoded audio in {:?}", decode_start.elapsed());
+        self.align_pcm(&samples, text, options)
+    }
+
+    /// Best-effort alignment of a known transcript to audio, given the audio is an
+    /// [f32] float array of codec `pcm_s16le` and in single-channel format.
+    /// See [`Model::align`].
+    ///
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// One [`Utterance`] per whitespace-separated word of `text`.
+    pub fn align_pcm(
+        &self,
+        audio: &[f32],
+        text: &str,
+        options: &TranscribeOptions,
+    ) -> Result<Vec<Utterance>, ModelError> {
+        let options = TranscribeOptions {
+            word_timestamps: true,
+            initial_prompt: options
+                .initial_prompt
+                .clone()
+                .or_else(|| Some(text.to_owned())),
+            ..options.clone()
+        };
+        let transcript = self.transcribe_pcm(audio, &options)?;
+        let tokens = transcript.word_utterances.unwrap_or_default();
+        let recognized = transcript::merge_tokens_into_words(&tokens);
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        trace!(
+            "Aligning {} words to {} recognised words",
+            words.len(),
+            recognized.len()
+        );
+        Ok(align_words(&words, &recognized))
+    }
+}
+
+/// Lowercase alphanumeric characters of a word, so punctuation and case don't
+/// prevent a match.
+fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Matches `words` to `recognized` with the longest common subsequence of their
+/// normalised text, then interpolates the timing of unmatched words.
+fn align_words(words: &[&str], recognized: &[Utterance]) -> Vec<Utterance> {
+    let known = words.iter().map(|word| normalize(word)).collect::<Vec<_>>();
+    let heard = recognized
+        .iter()
+        .map(|word| normalize(&word.text))
+        .collect::<Vec<_>>();
+
+    // lengths[i][j] is the length of the LCS of known[i..] and heard[j..].
+    let width = heard.len() + 1;
+    let mut lengths = vec![0u32; (known.len() + 1) * width];
+    for i in (0..known.len()).rev() {
+        for j in (0..heard.len()).rev() {
+            lengths[i * width + j] = if !known[i].is_empty() && known[i] == heard[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut matches = vec![None; known.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < known.len() && j < heard.len() {
+        if !known[i].is_empty() && known[i] == heard[j] {
+            matches[i] = Some(&recognized[j]);
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    let first_start = recognized.first().map_or(0, |word| word.start);
+    let last_stop = recognized.last().map_or(0, |word| word.stop);
+    let mut aligned: Vec<Utterance> = Vec::with_capacity(words.len());
+    let mut idx = 0;
+    while idx < words.len() {
+        if let Some(word) = matches[idx] {
+            aligned.push(Utterance {
+                start: word.start,
+                stop: word.stop,
+                text: words[idx].to_owned(),
+                probability: word.probability,
+                ..Utterance::default()
+            });
+            idx += 1;
+            continue;
+        }
+        // Spread a run of unmatched words evenly between its matched neighbours.
+        let run_end = matches[idx..]
+            .iter()
+            .position(Option::is_some)
+            .map_or(words.len(), |offset| idx + offset);
+        let start = aligned.last().map_or(first_start, |word| word.stop);
+        let stop = matches
+            .get(run_end)
+            .copied()
+            .flatten()
+            .map_or(last_stop, |word| word.start)
+            .max(start);
+        let run_length = i64::try_from(run_end - idx).unwrap_or(i64::MAX);
+        for (offset, word) in (0..).zip(&words[idx..run_end]) {
+            aligned.push(Utterance {
+                start: start + (stop - start) * offset / run_length,
+                stop: start + (stop - start) * (offset + 1) / run_length,
+                text: (*word).to_owned(),
+                ..Utterance::default()
+            });
+        }
+        idx = run_end;
+    }
+    aligned
+}
+
+#[test]
+fn test_align_words() {
+    let word = |start, stop, text: &str| Utterance {
+        start,
+        stop,
+        text: text.to_owned(),
+        probability: Some(1.0),
+        ..Utterance::default()
+    };
+    let recognized = [
+        word(0, 50, " And"),
+        word(50, 100, " so"),
+        word(100, 150, " my"),
+        word(200, 300, " Americans"),
+    ];
+    let aligned = align_words(&["And", "so,", "my", "fellow", "Americans."], &recognized);
+    let timings = aligned
+        .iter()
+        .map(|word| (word.text.as_str(), word.start, word.stop))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        timings,
+        vec![
+            ("And", 0, 50),
+            ("so,", 50, 100),
+            ("my", 100, 150),
+            ("fellow", 150, 200),
+            ("Americans.", 200, 300)
+        ]
+    );
+    assert_eq!(aligned[3].probability, None);
+}
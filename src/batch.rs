@@ -0,0 +1,110 @@
+use std::{
+    collections::HashSet,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    path::Path,
+};
+
+use log::{info, trace};
+
+use crate::{cache::cache_key, transcript::Transcript, Model, ModelError, TranscribeOptions};
+
+impl Model {
+    /// Transcribes a batch of audio files like [`Model::transcribe`], recording each
+    /// finished file in a manifest so that a batch that was interrupted, e.g. by a
+    /// crash, can be run again and only transcribe the files it hadn't finished.
+    ///
+    /// Each transcript is handed to `on_transcript` along with the path of its file;
+    /// store it there. Only once `on_transcript` returns is the file recorded as done,
+    /// so a crash while storing a transcript transcribes that file again next time.
+    ///
+    /// Files are recorded by a hash of their contents, the model and the options, as in
+    /// [`Model::transcribe_cached`], so renamed files are still skipped, while edited
+    /// files, or the same files with another model or options, are transcribed again.
+    /// The manifest is a text file with one line per finished file, which is only ever
+    /// appended to; delete it to start over.
+    ///
+    /// # Arguments
+    /// - `paths`: Audio files to transcribe.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// - `manifest`: File to record finished files in. Created if it doesn't exist.
+    /// - `on_transcript`: Called with the path and [`Transcript`] of each file
+    ///   transcribed.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::IoError`], if a file or the manifest can't be read, or the
+    ///       manifest can't be written.
+    ///     - [`ModelError::SerializationError`], if the options can't be serialized.
+    ///     - Any error returned by `on_transcript`, which stops the batch.
+    /// # Returns
+    /// The number of files transcribed, not counting those skipped.
+    pub fn transcribe_batch(
+        &self,
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        options: &TranscribeOptions,
+        manifest: impl AsRef<Path>,
+        mut on_transcript: impl FnMut(&Path, Transcript) -> Result<(), ModelError>,
+    ) -> Result<usize, ModelError> {
+        let manifest = manifest.as_ref();
+        let finished = match fs::read_to_string(manifest) {
+            Ok(contents) => parse_manifest(&contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(ModelError::IoError(e)),
+        };
+        let mut manifest_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(manifest)
+            .map_err(ModelError::IoError)?;
+        let options_json =
+            serde_json::to_string(options).map_err(ModelError::SerializationError)?;
+
+        let (mut transcribed, mut skipped) = (0, 0);
+        for path in paths {
+            let path = path.as_ref();
+            let audio = fs::read(path).map_err(ModelError::IoError)?;
+            let key = cache_key(&[self.name.as_bytes(), options_json.as_bytes(), &audio]);
+            if finished.contains(&key) {
+                trace!("Skipping {}, already transcribed", path.display());
+                skipped += 1;
+                continue;
+            }
+
+            trace!("Transcribing {}", path.display());
+            let transcript = self.transcribe(&audio, options)?;
+            on_transcript(path, transcript)?;
+            // The path is only there for whoever reads the manifest; files are
+            // recognized by their key.
+            writeln!(manifest_file, "{key:032x}\t{}", path.display())
+                .and_then(|()| manifest_file.sync_data())
+                .map_err(ModelError::IoError)?;
+            transcribed += 1;
+        }
+        info!("Transcribed {transcribed} files, skipped {skipped} already transcribed");
+        Ok(transcribed)
+    }
+}
+
+/// Keys of the files recorded in a manifest. Lines that don't start with a key, such
+/// as one cut short by a crash, are ignored, so their files are transcribed again.
+fn parse_manifest(contents: &str) -> HashSet<u128> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (key, _path) = line.split_once('\t')?;
+            (key.len() == 32)
+                .then(|| u128::from_str_radix(key, 16).ok())
+                .flatten()
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_manifest() {
+    let manifest = "0000000000000000000000000000002a\ta.wav\n\
+                    000000000000000000000000000000ff\tdir/b c.mp3\n\
+                    00000000000000000000000000000";
+    assert_eq!(parse_manifest(manifest), HashSet::from([0x2a, 0xff]));
+    assert!(parse_manifest("").is_empty());
+    assert!(parse_manifest("not a key\tc.wav\n").is_empty());
+}
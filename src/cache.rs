@@ -0,0 +1,199 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    path::Path,
+    sync::atomic::AtomicBool,
+};
+
+use log::{info, trace, warn};
+
+use whisper_rs::WhisperContext;
+
+use crate::{
+    transcript::Transcript, Model, ModelError, ModelType, TranscribeOptions,
+    WhisperContextParameters,
+};
+
+impl Model {
+    /// Loads the model from `cache_dir`, downloading it there first if it's missing.
+    /// If the cached file can't be loaded, e.g. because an earlier download was cut
+    /// short, it is deleted and downloaded again. The file is named after
+    /// [`ModelType::file_name`].
+    ///
+    /// Threads and processes loading the same model into the same `cache_dir` at once
+    /// take turns: the first downloads it while the others wait, then load the cached
+    /// file. The turns are taken with a lock on a `.bin.lock` file next to the model,
+    /// which is left in place.
+    ///
+    /// # Arguments
+    /// - `model`: [`ModelType`].
+    /// - `cache_dir`: Directory to cache models in. Created if it doesn't exist.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::WhisperError`], if the freshly downloaded model can't be
+    ///       loaded either.
+    ///     - [`ModelError::DownloadError`],
+    ///     - [`ModelError::IoError`], if the cache can't be read or written.
+    pub fn load_or_download(
+        model: &ModelType,
+        cache_dir: impl AsRef<Path>,
+    ) -> Result<Self, ModelError> {
+        let cache_dir = cache_dir.as_ref();
+        let path = cache_dir.join(model.file_name());
+        fs::create_dir_all(cache_dir).map_err(ModelError::IoError)?;
+        // Held until the model is loaded, so nobody else writes, or deletes, the file
+        // in the meantime.
+        let _lock = lock(&path.with_extension("bin.lock"))?;
+        if path.is_file() {
+            match Self::new_with_context_params(
+                &path.to_string_lossy(),
+                WhisperContextParameters::default(),
+            ) {
+                Ok(mut cached) => {
+                    if cached.n_mels() != model.n_mels() {
+                        warn!(
+                            "Cached model {} has {} mel bands, but {model} has {}",
+                            path.display(),
+                            cached.n_mels(),
+                            model.n_mels()
+                        );
+                    }
+                    model.file_name().clone_into(&mut cached.name);
+                    return Ok(cached);
+                }
+                Err(e) => {
+                    warn!(
+                        "Cached model {} is invalid ({e:?}), downloading it again",
+                        path.display()
+                    );
+                    fs::remove_file(&path).map_err(ModelError::IoError)?;
+                }
+            }
+        }
+
+        let bytes = Self::download_bytes(model, &ureq::agent(), &AtomicBool::new(false), None)?;
+        // Write to a temporary file first, so an interrupted write never leaves a
+        // truncated model behind under the real name.
+        let partial_path = path.with_extension("bin.part");
+        fs::write(&partial_path, &bytes).map_err(ModelError::IoError)?;
+        fs::rename(&partial_path, &path).map_err(ModelError::IoError)?;
+        info!("Cached model {model} at {}", path.display());
+
+        Ok(Self {
+            context: WhisperContext::new_from_buffer_with_params(
+                &bytes,
+                WhisperContextParameters::default(),
+            )
+            .map_err(ModelError::WhisperError)?,
+            name: model.file_name().to_owned(),
+        })
+    }
+
+    /// Transcribes audio like [`Model::transcribe`], but stores the [`Transcript`] in
+    /// `cache_dir` and returns the stored one when the same audio is transcribed again
+    /// with the same model and options. Handy for test suites that transcribe the same
+    /// fixtures over and over.
+    ///
+    /// Entries are keyed by a hash of the audio bytes, the version of mutter, the
+    /// model's path, or file name if it was downloaded, and every option. Replacing a
+    /// model file at the same path doesn't invalidate its entries; clear `cache_dir`
+    /// yourself if you do. Entries are never evicted.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// - `cache_dir`: Directory to cache transcripts in. Created if it doesn't exist.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::IoError`], if the cache can't be written.
+    ///     - [`ModelError::SerializationError`], if the options or transcript can't be
+    ///       serialized.
+    /// # Returns
+    /// [Transcript]. On a hit, its timings are those of the original transcription.
+    pub fn transcribe_cached(
+        &self,
+        audio: impl AsRef<[u8]>,
+        options: &TranscribeOptions,
+        cache_dir: impl AsRef<Path>,
+    ) -> Result<Transcript, ModelError> {
+        let audio = audio.as_ref();
+        let options_json =
+            serde_json::to_string(options).map_err(ModelError::SerializationError)?;
+        // Another version of mutter may transcribe the same audio differently.
+        let version = env!("CARGO_PKG_VERSION");
+        let key = cache_key(&[
+            version.as_bytes(),
+            self.name.as_bytes(),
+            options_json.as_bytes(),
+            audio,
+        ]);
+        let path = cache_dir.as_ref().join(format!("{key:032x}.json"));
+
+        if let Ok(cached) = fs::read(&path) {
+            match serde_json::from_slice(&cached) {
+                Ok(transcript) => {
+                    trace!("Using cached transcript {}", path.display());
+                    return Ok(transcript);
+                }
+                Err(e) => warn!(
+                    "Cached transcript {} is invalid ({e}), transcribing again",
+                    path.display()
+                ),
+            }
+        }
+
+        let transcript = self.transcribe(audio, options)?;
+        let json = serde_json::to_vec(&transcript).map_err(ModelError::SerializationError)?;
+        fs::create_dir_all(cache_dir).map_err(ModelError::IoError)?;
+        // Write to a temporary file first, so a concurrent reader never sees half a
+        // transcript.
+        let partial_path = path.with_extension("json.part");
+        fs::write(&partial_path, json).map_err(ModelError::IoError)?;
+        fs::rename(&partial_path, &path).map_err(ModelError::IoError)?;
+        Ok(transcript)
+    }
+}
+
+/// Takes an exclusive lock on the file at `path`, creating it if needed, and waiting
+/// for whoever holds it to let go. The lock is released when the returned file is
+/// dropped, or when the process holding it exits.
+fn lock(path: &Path) -> Result<File, ModelError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(ModelError::IoError)?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            info!("Waiting for {} to be unlocked", path.display());
+            file.lock().map_err(ModelError::IoError)?;
+        }
+        Err(TryLockError::Error(e)) => return Err(ModelError::IoError(e)),
+    }
+    Ok(file)
+}
+
+/// 128-bit FNV-1a hash of `parts`, separated so that moving bytes from one part to the
+/// next changes the hash. Unlike [`std::hash::DefaultHasher`], it is stable across
+/// Rust versions, so cache entries survive compiler upgrades.
+pub(crate) fn cache_key(parts: &[&[u8]]) -> u128 {
+    const OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+    let mut hash = OFFSET_BASIS;
+    for part in parts {
+        for &byte in part.iter().chain(&(part.len() as u64).to_le_bytes()) {
+            hash ^= u128::from(byte);
+            hash = hash.wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
+#[test]
+fn test_cache_key() {
+    assert_eq!(cache_key(&[b"ab", b"c"]), cache_key(&[b"ab", b"c"]));
+    assert_ne!(cache_key(&[b"ab", b"c"]), cache_key(&[b"a", b"bc"]));
+    assert_ne!(cache_key(&[b"ab"]), cache_key(&[b"ba"]));
+}
@@ -0,0 +1,399 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{info, trace};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    pool::StatePool,
+    transcode,
+    transcript::{self, Timings, Transcript},
+    vad, Model, ModelError, TranscribeOptions,
+};
+
+/// Result of a chunked transcription. See [`Model::transcribe_chunked`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChunkedTranscript {
+    /// Transcript of every chunk that was completed.
+    pub transcript: Transcript,
+    /// Whether the transcription was cancelled before all chunks were completed.
+    pub cancelled: bool,
+}
+
+impl Model {
+    /// Transcribes audio in chunks of `chunk_length`, given the audio is a byte array
+    /// of a file. Between chunks, `abort` is checked; if it has been set, the chunks
+    /// completed so far are returned instead of being discarded.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `chunk_length`: Length of each chunk of audio fed to whisper.
+    /// - `options`: [`TranscribeOptions`] to transcribe every chunk with.
+    /// - `abort`: Set this to `true`, from any thread, to cancel the transcription.
+    ///   The chunk currently being transcribed is finished first.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::InvalidRange`], if `chunk_length` is zero.
+    /// # Returns
+    /// [`ChunkedTranscript`]
+    pub fn transcribe_chunked(
+        &self,
+        audio: impl AsRef<[u8]>,
+        chunk_length: Duration,
+        options: &TranscribeOptions,
+        abort: &AtomicBool,
+    ) -> Result<ChunkedTranscript, ModelError> {
+        trace!("Decoding audio.");
+        let decode_start = Instant::now();
+        let decoded =
+            transcode::decode_up_to(audio.as_ref().to_vec(), options.max_transcribe_duration)?;
+        let decode_time = decode_start.elapsed();
+        let mut chunked =
+            self.transcribe_pcm_chunked(&decoded.samples, chunk_length, options, abort)?;
+        chunked.transcript.timings.decode = decode_time;
+        if !chunked.cancelled {
+            decoded.annotate(&mut chunked.transcript);
+        }
+        Ok(chunked)
+    }
+
+    /// Transcribes audio in chunks of `chunk_length`, given the audio is an [f32] float
+    /// array of codec `pcm_s16le` and in single-channel format.
+    /// See [`Model::transcribe_chunked`].
+    ///
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::InvalidRange`], if `chunk_length` is zero.
+    /// # Returns
+    /// [`ChunkedTranscript`]
+    pub fn transcribe_pcm_chunked(
+        &self,
+        audio: &[f32],
+        chunk_length: Duration,
+        options: &TranscribeOptions,
+        abort: &AtomicBool,
+    ) -> Result<ChunkedTranscript, ModelError> {
+        let chunk_samples = transcode::duration_to_samples(chunk_length);
+        if chunk_samples == 0 {
+            return Err(ModelError::InvalidRange);
+        }
+
+        let mut transcript = Transcript {
+            processing_time: Duration::ZERO,
+            utterances: Vec::new(),
+            word_utterances: options.word_timestamps.then(Vec::new),
+            timings: Timings::default(),
+            audio_duration: Some(Duration::ZERO),
+            clipping_ratio: None,
+            source_format: None,
+            speech_ranges: None,
+        };
+        for (idx, chunk) in audio.chunks(chunk_samples).enumerate() {
+            if abort.load(Ordering::Relaxed) {
+                info!("Transcription cancelled after {idx} chunks");
+                return Ok(ChunkedTranscript {
+                    transcript,
+                    cancelled: true,
+                });
+            }
+            trace!("Transcribing chunk {idx}");
+            let mut chunk_transcript = self.transcribe_pcm(chunk, options)?;
+            let offset = transcode::samples_to_duration(idx * chunk_samples);
+            chunk_transcript.offset_by(transcript::duration_to_raw(offset));
+            transcript.append(chunk_transcript);
+        }
+
+        Ok(ChunkedTranscript {
+            transcript,
+            cancelled: false,
+        })
+    }
+
+    /// Transcribes audio in pieces of at most `piece_length`, each cut at a quiet
+    /// moment. If `carry_context` is set, the end of each piece's text is the prompt
+    /// for the next; otherwise whisper starts every piece with a fresh context.
+    /// See [`TranscribeOptions::reset_context_every`] and
+    /// [`TranscribeOptions::chunk_longer_than`].
+    pub(crate) fn transcribe_pcm_in_pieces<'a>(
+        &'a self,
+        audio: &[f32],
+        piece_length: Duration,
+        carry_context: bool,
+        options: &TranscribeOptions,
+        states: Option<&StatePool<'a>>,
+    ) -> Result<Transcript, ModelError> {
+        if piece_length.is_zero() {
+            return Err(ModelError::InvalidRange);
+        }
+        let mut piece_options = TranscribeOptions {
+            reset_context_every: None,
+            chunk_longer_than: None,
+            ..options.clone()
+        };
+
+        let mut transcript = Transcript {
+            word_utterances: options.word_timestamps.then(Vec::new),
+            audio_duration: Some(Duration::ZERO),
+            ..Transcript::default()
+        };
+        let mut start = 0;
+        for end in vad::split_points(audio, piece_length)
+            .into_iter()
+            .chain([audio.len()])
+        {
+            self.transcribe_piece(
+                &audio[start..end],
+                start,
+                carry_context,
+                &mut piece_options,
+                &mut transcript,
+                states,
+            )?;
+            start = end;
+        }
+        Ok(transcript)
+    }
+
+    /// Transcribes audio in pieces of at most `piece_length` like
+    /// [`TranscribeOptions::chunk_longer_than`], given the audio is a byte array of a
+    /// file, while it is still being decoded. A second thread decodes the audio and
+    /// hands it over as it goes, so on multi-core machines decoding mostly overlaps
+    /// with transcribing rather than having to finish first. Unless `no_context` is
+    /// `true`, the end of each piece's text is carried over as the prompt for the
+    /// next.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `piece_length`: Longest piece of audio fed to whisper at once. Each piece is
+    ///   cut at the quietest moment near this limit.
+    /// - `options`: [`TranscribeOptions`] to transcribe every piece with.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::InvalidRange`], if `piece_length` is zero.
+    /// # Returns
+    /// [Transcript]. `timings.decode` is the time the decoding thread took, most of
+    /// which overlapped with inference.
+    pub fn transcribe_pipelined(
+        &self,
+        audio: impl AsRef<[u8]>,
+        piece_length: Duration,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        let piece_samples = transcode::duration_to_samples(piece_length);
+        if piece_samples == 0 {
+            return Err(ModelError::InvalidRange);
+        }
+        let bytes = audio.as_ref().to_vec();
+        let block_samples = transcode::duration_to_samples(Duration::from_secs(1));
+        // Let decoding run up to two pieces ahead of transcription.
+        let (sender, receiver) = mpsc::sync_channel(2 * piece_samples.div_ceil(block_samples));
+        let carry_context = !options.no_context;
+        let mut piece_options = TranscribeOptions {
+            reset_context_every: None,
+            chunk_longer_than: None,
+            ..options.clone()
+        };
+        let mut transcript = Transcript {
+            word_utterances: options.word_timestamps.then(Vec::new),
+            audio_duration: Some(Duration::ZERO),
+            ..Transcript::default()
+        };
+
+        let (streamed, decoded) = thread::scope(|scope| {
+            let decode_thread = scope.spawn(move || {
+                let decode_start = Instant::now();
+                let duration = transcode::decode_blocks(bytes, block_samples, &sender)?;
+                Ok((duration, decode_start.elapsed()))
+            });
+            // Drops the receiver when done, so the decoder stops even if this fails.
+            let streamed = self.transcribe_blocks(
+                receiver,
+                piece_length,
+                carry_context,
+                &mut piece_options,
+                &mut transcript,
+            );
+            let decoded: Result<_, ModelError> = decode_thread
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            (streamed, decoded)
+        });
+        let (start, rest) = streamed?;
+        let (duration, decode_time) = decoded?;
+        if start == 0 || !rest.is_empty() {
+            self.transcribe_piece(
+                &rest,
+                start,
+                carry_context,
+                &mut piece_options,
+                &mut transcript,
+                None,
+            )?;
+        }
+        transcript.timings.decode = decode_time;
+        transcript.audio_duration = Some(duration);
+        Ok(transcript)
+    }
+
+    /// Transcribes pieces of at most `piece_length` as soon as enough decoded blocks
+    /// have been received. Returns the index of the first sample that hasn't been
+    /// transcribed yet, and the samples from there on, which make up less than a
+    /// piece.
+    fn transcribe_blocks(
+        &self,
+        blocks: Receiver<Vec<f32>>,
+        piece_length: Duration,
+        carry_context: bool,
+        piece_options: &mut TranscribeOptions,
+        transcript: &mut Transcript,
+    ) -> Result<(usize, Vec<f32>), ModelError> {
+        let piece_samples = transcode::duration_to_samples(piece_length);
+        let mut buffer = Vec::new();
+        let mut start = 0;
+        for block in blocks {
+            buffer.extend(block);
+            while buffer.len() > piece_samples {
+                let end = vad::split_points(&buffer, piece_length)[0];
+                self.transcribe_piece(
+                    &buffer[..end],
+                    start,
+                    carry_context,
+                    piece_options,
+                    transcript,
+                    None,
+                )?;
+                buffer.drain(..end);
+                start += end;
+            }
+        }
+        Ok((start, buffer))
+    }
+
+    /// Transcribes one piece of audio that starts at sample `start` of the whole
+    /// recording, and appends it to `transcript`. If `carry_context` is set, the end
+    /// of its text becomes the prompt for the next piece, trimmed to as many of the
+    /// most recent tokens as whisper conditions on. Takes its whisper state from
+    /// `states` if given.
+    pub(crate) fn transcribe_piece<'a>(
+        &'a self,
+        piece: &[f32],
+        start: usize,
+        carry_context: bool,
+        piece_options: &mut TranscribeOptions,
+        transcript: &mut Transcript,
+        states: Option<&StatePool<'a>>,
+    ) -> Result<(), ModelError> {
+        trace!("Transcribing samples {start}..{}", start + piece.len());
+        let mut piece_transcript = self.transcribe_pcm_using(piece, piece_options, states)?;
+        if carry_context {
+            let max_tokens = self.max_prompt_tokens();
+            let text = piece_transcript.as_text();
+            let prompt =
+                carryover_prompt(&text, |prompt| Ok(self.count_tokens(prompt)? <= max_tokens))?;
+            if let Some(prompt) = prompt {
+                piece_options.initial_prompt = Some(prompt.to_owned());
+            }
+        } else {
+            piece_options.initial_prompt = None;
+        }
+        let offset = transcode::samples_to_duration(start);
+        piece_transcript.offset_by(transcript::duration_to_raw(offset));
+        transcript.append(piece_transcript);
+        Ok(())
+    }
+
+    /// Most tokens of prompt whisper conditions on: half of its text context. It drops
+    /// the tokens before those.
+    fn max_prompt_tokens(&self) -> usize {
+        self.n_text_ctx() / 2
+    }
+
+    /// Number of tokens `text` is tokenized into.
+    fn count_tokens(&self, text: &str) -> Result<usize, ModelError> {
+        // Every token is at least one byte long.
+        let tokens = self
+            .context
+            .tokenize(text, text.len())
+            .map_err(ModelError::WhisperError)?;
+        Ok(tokens.len())
+    }
+}
+
+/// The end of a piece's text to prompt the next piece with: the longest end for which
+/// `fits` holds, starting at a word boundary unless the text has no spaces to start
+/// at, e.g. in Chinese or Japanese. It's always cut at a character boundary. `None`
+/// if the piece has no text, or not even its last character fits.
+fn carryover_prompt<E>(
+    text: &str,
+    mut fits: impl FnMut(&str) -> Result<bool, E>,
+) -> Result<Option<&str>, E> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    if fits(text)? {
+        return Ok(Some(text));
+    }
+    // The text from `boundaries[too_long]` on doesn't fit, and the text from
+    // `boundaries[short_enough]` on does, or is empty.
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    let (mut too_long, mut short_enough) = (0, boundaries.len());
+    while short_enough - too_long > 1 {
+        let middle = too_long + (short_enough - too_long) / 2;
+        if fits(&text[boundaries[middle]..])? {
+            short_enough = middle;
+        } else {
+            too_long = middle;
+        }
+    }
+    let Some(&cut) = boundaries.get(short_enough) else {
+        return Ok(None);
+    };
+    let start = if text[..cut].ends_with(char::is_whitespace) {
+        cut
+    } else {
+        text[cut..]
+            .find(char::is_whitespace)
+            .map_or(cut, |offset| cut + offset)
+    };
+    Ok(Some(text[start..].trim_start()))
+}
+
+#[test]
+fn test_carryover_prompt() {
+    let at_most = |bytes: usize| move |text: &str| Ok::<_, ()>(text.len() <= bytes);
+    assert_eq!(carryover_prompt(" \n", at_most(10)), Ok(None));
+    assert_eq!(
+        carryover_prompt(" And so,\nmy fellow\n", at_most(100)),
+        Ok(Some("And so,\nmy fellow"))
+    );
+    assert_eq!(
+        carryover_prompt(" And so,\nmy fellow\n", at_most(12)),
+        Ok(Some("my fellow"))
+    );
+    assert_eq!(
+        carryover_prompt("And so, my fellow", at_most(8)),
+        Ok(Some("fellow"))
+    );
+    // Without spaces, cut at the last character boundary that fits.
+    assert_eq!(
+        carryover_prompt("我们今天要讨论的问题", at_most(10)),
+        Ok(Some("的问题"))
+    );
+    assert_eq!(carryover_prompt("问题", at_most(2)), Ok(None));
+    let long = "word ".repeat(1000);
+    let prompt = carryover_prompt(&long, at_most(500)).unwrap().unwrap();
+    assert_eq!(prompt.len(), 499);
+    assert!(prompt.starts_with("word"));
+    assert!(prompt.ends_with("word"));
+    assert_eq!(
+        carryover_prompt(&long, |_| Err("no tokenizer")),
+        Err("no tokenizer")
+    );
+}
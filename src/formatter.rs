@@ -0,0 +1,91 @@
+//! Pluggable output formats for a [`Transcript`].
+//!
+//! Implement [`TranscriptFormatter`] for your own format and pass it to
+//! [`Transcript::format_with`]. Closures taking a `&Transcript` and returning a
+//! [`String`] are formatters too.
+
+use crate::transcript::Transcript;
+
+/// Formats a [`Transcript`] as a string.
+pub trait TranscriptFormatter {
+    /// Formats the transcript.
+    fn format(&self, transcript: &Transcript) -> String;
+}
+
+impl<F> TranscriptFormatter for F
+where
+    F: Fn(&Transcript) -> String,
+{
+    fn format(&self, transcript: &Transcript) -> String {
+        self(transcript)
+    }
+}
+
+/// Plain text, one utterance per line. See [`Transcript::as_text`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Text;
+
+impl TranscriptFormatter for Text {
+    fn format(&self, transcript: &Transcript) -> String {
+        transcript.as_text()
+    }
+}
+
+/// SRT subtitles. See [`Transcript::as_srt`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Srt {
+    /// Round every time to the nearest frame boundary at this many frames per second.
+    /// See [`Transcript::as_srt_at_fps`].
+    pub snap_to_fps: Option<f64>,
+}
+
+impl TranscriptFormatter for Srt {
+    fn format(&self, transcript: &Transcript) -> String {
+        match self.snap_to_fps {
+            Some(fps) => transcript.as_srt_at_fps(fps),
+            None => transcript.as_srt(),
+        }
+    }
+}
+
+/// VTT subtitles. See [`Transcript::as_vtt`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Vtt {
+    /// Round every time to the nearest frame boundary at this many frames per second.
+    /// See [`Transcript::as_vtt_at_fps`].
+    pub snap_to_fps: Option<f64>,
+}
+
+impl TranscriptFormatter for Vtt {
+    fn format(&self, transcript: &Transcript) -> String {
+        match self.snap_to_fps {
+            Some(fps) => transcript.as_vtt_at_fps(fps),
+            None => transcript.as_vtt(),
+        }
+    }
+}
+
+#[test]
+fn test_format_with() {
+    let transcript = Transcript {
+        utterances: vec![crate::transcript::Utterance {
+            start: 0,
+            stop: 100,
+            text: " Hi".to_string(),
+            ..Default::default()
+        }],
+        ..Transcript::default()
+    };
+    assert_eq!(transcript.format_with(&Srt::default()), transcript.as_srt());
+    assert_eq!(transcript.format_with(&Vtt::default()), transcript.as_vtt());
+    let snapped = Srt {
+        snap_to_fps: Some(25.0),
+    };
+    assert_eq!(
+        transcript.format_with(&snapped),
+        transcript.as_srt_at_fps(25.0)
+    );
+    assert_eq!(transcript.format_with(&Text), "Hi\n");
+    let shout = |transcript: &Transcript| transcript.join_with(" ").to_uppercase();
+    assert_eq!(transcript.format_with(&shout), "HI");
+}
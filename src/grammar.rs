@@ -0,0 +1,496 @@
+//! GBNF grammars to constrain what whisper can output.
+//!
+//! A grammar is written in the same GBNF syntax as whisper.cpp and llama.cpp use, for
+//! example to only accept a fixed set of voice commands:
+//!
+//! ```text
+//! root   ::= " " command "."?
+//! command ::= "lights on" | "lights off" | "play" [a-z ]+
+//! ```
+//!
+//! Supported are string literals, character classes such as `[a-z]` and `[^0-9]`,
+//! references to other rules, groups in parentheses, alternatives with `|`, the
+//! `*`, `+` and `?` repetitions and `#` comments. Parsing starts at the `root` rule.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use whisper_rs::{WhisperGrammarElement, WhisperGrammarElementType};
+
+use crate::ModelError;
+
+/// A parsed GBNF grammar. Set it as
+/// [`TranscribeOptions::grammar`](crate::TranscribeOptions::grammar) to constrain the
+/// transcription to text the grammar accepts.
+///
+/// Serializes as its GBNF source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grammar {
+    source: String,
+    rules: Vec<Vec<Element>>,
+    root: usize,
+}
+
+/// One element of a rule, as whisper.cpp represents grammars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Element {
+    /// End of the rule.
+    End,
+    /// Start of another alternative of the rule.
+    Alternate,
+    /// Reference to the rule with this id.
+    RuleReference(u32),
+    /// A character, or the start of a character class.
+    Character(u32),
+    /// The start of a negated character class.
+    NotCharacter(u32),
+    /// Upper bound of a range started by the previous character.
+    CharacterRangeUpper(u32),
+    /// Another character of a character class.
+    CharacterAlternate(u32),
+}
+
+impl Grammar {
+    /// Parses a grammar in GBNF syntax. See the [module docs](self).
+    ///
+    /// # Errors
+    /// - [`ModelError::InvalidGrammar`], if the grammar can't be parsed, a rule is
+    ///   used but never defined, or there is no `root` rule.
+    pub fn parse(source: &str) -> Result<Self, ModelError> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+            symbols: HashMap::new(),
+            rules: Vec::new(),
+        };
+        parser.parse_space(true);
+        while parser.pos < parser.chars.len() {
+            parser.parse_rule()?;
+        }
+
+        let root = *parser
+            .symbols
+            .get("root")
+            .ok_or_else(|| ModelError::InvalidGrammar("no root rule".to_owned()))?;
+        let mut rules = Vec::with_capacity(parser.rules.len());
+        for (id, rule) in parser.rules.into_iter().enumerate() {
+            let rule = rule.ok_or_else(|| {
+                let name = parser
+                    .symbols
+                    .iter()
+                    .find(|&(_, &symbol)| symbol == id)
+                    .map_or("", |(name, _)| name.as_str());
+                ModelError::InvalidGrammar(format!("undefined rule {name}"))
+            })?;
+            rules.push(rule);
+        }
+        Ok(Self {
+            source: source.to_owned(),
+            rules,
+            root,
+        })
+    }
+
+    /// A grammar that only accepts one of `phrases`, with whisper's usual leading
+    /// space and an optional full stop. Handy for a fixed set of voice commands.
+    ///
+    /// # Panics
+    /// Never: quoted phrases always parse.
+    #[must_use]
+    pub fn one_of<S: AsRef<str>>(phrases: &[S]) -> Self {
+        let alternatives = phrases
+            .iter()
+            .map(|phrase| quote(phrase.as_ref()))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let source = format!("root ::= \" \" ({alternatives}) \".\"?\n");
+        Self::parse(&source).expect("generated grammar should parse")
+    }
+
+    /// GBNF source the grammar was parsed from.
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Index of the `root` rule, where whisper starts matching.
+    pub(crate) const fn root(&self) -> usize {
+        self.root
+    }
+
+    /// The rules in whisper-rs' representation, each terminated by an end element.
+    pub(crate) fn elements(&self) -> Vec<WhisperGrammarElement> {
+        self.rules
+            .iter()
+            .flatten()
+            .map(|element| {
+                let (element_type, value) = match *element {
+                    Element::End => (WhisperGrammarElementType::End, 0),
+                    Element::Alternate => (WhisperGrammarElementType::Alternate, 0),
+                    Element::RuleReference(id) => (WhisperGrammarElementType::RuleReference, id),
+                    Element::Character(c) => (WhisperGrammarElementType::Character, c),
+                    Element::NotCharacter(c) => (WhisperGrammarElementType::NotCharacter, c),
+                    Element::CharacterRangeUpper(c) => {
+                        (WhisperGrammarElementType::CharacterRangeUpper, c)
+                    }
+                    Element::CharacterAlternate(c) => {
+                        (WhisperGrammarElementType::CharacterAlternate, c)
+                    }
+                };
+                WhisperGrammarElement::new(element_type, value)
+            })
+            .collect()
+    }
+}
+
+impl Serialize for Grammar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Grammar {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Self::parse(&source).map_err(|err| serde::de::Error::custom(format!("{err:?}")))
+    }
+}
+
+/// Quotes text as a GBNF string literal.
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Recursive descent parser for GBNF, following whisper.cpp's grammar parser.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Id of every rule name seen so far, whether defined yet or not.
+    symbols: HashMap<String, usize>,
+    /// Rules by id. `None` until the rule is defined.
+    rules: Vec<Option<Vec<Element>>>,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> ModelError {
+        ModelError::InvalidGrammar(format!("{message} at character {}", self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Skips whitespace and comments. Newlines are only skipped if `newlines` is set,
+    /// as a newline can end a rule.
+    fn parse_space(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            if c == '#' {
+                while self.peek().is_some_and(|c| c != '\n' && c != '\r') {
+                    self.pos += 1;
+                }
+            } else if c == ' ' || c == '\t' || (newlines && (c == '\n' || c == '\r')) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, ModelError> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(self.error("expected a rule name"));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// Id of the rule with the given name, reserving one if it's new.
+    fn symbol(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.symbols.get(name) {
+            return id;
+        }
+        let id = self.rules.len();
+        self.symbols.insert(name.to_owned(), id);
+        self.rules.push(None);
+        id
+    }
+
+    /// Reserves an id for a rule generated for a group or repetition.
+    fn generate_symbol(&mut self, base: &str) -> usize {
+        let name = format!("{base}_{}", self.rules.len());
+        self.symbol(&name)
+    }
+
+    fn parse_rule(&mut self) -> Result<(), ModelError> {
+        let name = self.parse_name()?;
+        self.parse_space(false);
+        for expected in "::=".chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error("expected ::="));
+            }
+            self.pos += 1;
+        }
+        self.parse_space(true);
+
+        let id = self.symbol(&name);
+        let elements = self.parse_alternates(&name, false)?;
+        self.rules[id] = Some(elements);
+
+        match self.peek() {
+            Some('\r' | '\n') | None => {
+                self.parse_space(true);
+                Ok(())
+            }
+            Some(_) => Err(self.error("expected a newline or end of input")),
+        }
+    }
+
+    /// Parses `|`-separated sequences, and returns them as a rule ending in
+    /// [`Element::End`].
+    fn parse_alternates(&mut self, name: &str, nested: bool) -> Result<Vec<Element>, ModelError> {
+        let mut elements = Vec::new();
+        self.parse_sequence(name, nested, &mut elements)?;
+        while self.peek() == Some('|') {
+            elements.push(Element::Alternate);
+            self.pos += 1;
+            self.parse_space(true);
+            self.parse_sequence(name, nested, &mut elements)?;
+        }
+        elements.push(Element::End);
+        Ok(elements)
+    }
+
+    fn parse_sequence(
+        &mut self,
+        name: &str,
+        nested: bool,
+        elements: &mut Vec<Element>,
+    ) -> Result<(), ModelError> {
+        // Start of the last item, which a repetition operator applies to.
+        let mut last_item = elements.len();
+        while let Some(c) = self.peek() {
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    last_item = elements.len();
+                    while self.peek() != Some('"') {
+                        let c = self.parse_char()?;
+                        elements.push(Element::Character(c));
+                    }
+                    self.pos += 1;
+                    self.parse_space(nested);
+                }
+                '[' => {
+                    self.pos += 1;
+                    last_item = elements.len();
+                    let negated = self.peek() == Some('^');
+                    if negated {
+                        self.pos += 1;
+                    }
+                    let mut first = true;
+                    while self.peek() != Some(']') {
+                        let c = self.parse_char()?;
+                        elements.push(match (first, negated) {
+                            (true, true) => Element::NotCharacter(c),
+                            (true, false) => Element::Character(c),
+                            (false, _) => Element::CharacterAlternate(c),
+                        });
+                        first = false;
+                        if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                            self.pos += 1;
+                            let upper = self.parse_char()?;
+                            elements.push(Element::CharacterRangeUpper(upper));
+                        }
+                    }
+                    self.pos += 1;
+                    self.parse_space(nested);
+                }
+                '(' => {
+                    self.pos += 1;
+                    self.parse_space(true);
+                    let id = self.generate_symbol(name);
+                    let group = self.parse_alternates(name, true)?;
+                    self.rules[id] = Some(group);
+                    last_item = elements.len();
+                    elements.push(Element::RuleReference(rule_id(id)?));
+                    if self.peek() != Some(')') {
+                        return Err(self.error("expected )"));
+                    }
+                    self.pos += 1;
+                    self.parse_space(nested);
+                }
+                '*' | '+' | '?' => {
+                    if last_item == elements.len() {
+                        return Err(self.error("expected an item before a repetition"));
+                    }
+                    self.pos += 1;
+                    self.parse_space(nested);
+                    self.repeat(name, c, last_item, elements)?;
+                }
+                c if c.is_ascii_alphanumeric() || c == '-' || c == '_' => {
+                    let reference = self.parse_name()?;
+                    let id = self.symbol(&reference);
+                    last_item = elements.len();
+                    elements.push(Element::RuleReference(rule_id(id)?));
+                    self.parse_space(nested);
+                }
+                _ => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces the item at `elements[last_item..]` by a reference to a generated
+    /// rule that repeats it, as whisper.cpp's grammars have no repetition operators:
+    /// - `x*` becomes `generated ::= x generated |`
+    /// - `x+` becomes `x generated`, with `generated` as for `x*`
+    /// - `x?` becomes `generated ::= x |`
+    fn repeat(
+        &mut self,
+        name: &str,
+        operator: char,
+        last_item: usize,
+        elements: &mut Vec<Element>,
+    ) -> Result<(), ModelError> {
+        let item = elements.split_off(last_item);
+        let id = self.generate_symbol(name);
+        let reference = Element::RuleReference(rule_id(id)?);
+
+        let mut rule = item.clone();
+        if operator != '?' {
+            rule.push(reference);
+        }
+        rule.extend([Element::Alternate, Element::End]);
+        self.rules[id] = Some(rule);
+
+        if operator == '+' {
+            elements.extend(item);
+        }
+        elements.push(reference);
+        Ok(())
+    }
+
+    /// Parses one possibly escaped character of a literal or character class.
+    fn parse_char(&mut self) -> Result<u32, ModelError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("unexpected end of input"))?;
+        self.pos += 1;
+        if c != '\\' {
+            return Ok(u32::from(c));
+        }
+        let escaped = self
+            .peek()
+            .ok_or_else(|| self.error("unexpected end of input"))?;
+        self.pos += 1;
+        match escaped {
+            'n' => Ok(u32::from('\n')),
+            'r' => Ok(u32::from('\r')),
+            't' => Ok(u32::from('\t')),
+            'x' => {
+                let digits = self
+                    .chars
+                    .get(self.pos..self.pos + 2)
+                    .ok_or_else(|| self.error("expected two hex digits"))?
+                    .iter()
+                    .collect::<String>();
+                self.pos += 2;
+                u32::from_str_radix(&digits, 16).map_err(|_| self.error("invalid hex escape"))
+            }
+            '\\' | '"' | '[' | ']' | '-' | '^' => Ok(u32::from(escaped)),
+            _ => Err(self.error("unknown escape")),
+        }
+    }
+}
+
+/// Converts a rule id to whisper's representation.
+fn rule_id(id: usize) -> Result<u32, ModelError> {
+    u32::try_from(id).map_err(|_| ModelError::InvalidGrammar("too many rules".to_owned()))
+}
+
+#[test]
+fn test_parse_grammar() {
+    let grammar = Grammar::parse(
+        "# Voice commands\n\
+         root ::= \" \" command \".\"?\n\
+         command ::= \"on\" | [a-c^]+\n",
+    )
+    .unwrap();
+    assert_eq!(grammar.root(), 0);
+    let c = |c: char| u32::from(c);
+    assert_eq!(
+        grammar.rules,
+        vec![
+            vec![
+                Element::Character(c(' ')),
+                Element::RuleReference(1),
+                Element::RuleReference(2),
+                Element::End
+            ],
+            vec![
+                Element::Character(c('o')),
+                Element::Character(c('n')),
+                Element::Alternate,
+                Element::Character(c('a')),
+                Element::CharacterRangeUpper(c('c')),
+                Element::CharacterAlternate(c('^')),
+                Element::RuleReference(3),
+                Element::End
+            ],
+            vec![Element::Character(c('.')), Element::Alternate, Element::End],
+            vec![
+                Element::Character(c('a')),
+                Element::CharacterRangeUpper(c('c')),
+                Element::CharacterAlternate(c('^')),
+                Element::RuleReference(3),
+                Element::Alternate,
+                Element::End
+            ],
+        ]
+    );
+    assert_eq!(grammar.elements().len(), 21);
+
+    assert!(matches!(
+        Grammar::parse("root ::= command\n"),
+        Err(ModelError::InvalidGrammar(_))
+    ));
+    assert!(matches!(
+        Grammar::parse("commands ::= \"on\"\n"),
+        Err(ModelError::InvalidGrammar(_))
+    ));
+    assert!(matches!(
+        Grammar::parse("root ::= (\"on\"\n"),
+        Err(ModelError::InvalidGrammar(_))
+    ));
+}
+
+#[test]
+fn test_one_of() {
+    let grammar = Grammar::one_of(&["lights on", "say \"hi\""]);
+    assert_eq!(
+        grammar.source(),
+        "root ::= \" \" (\"lights on\" | \"say \\\"hi\\\"\") \".\"?\n"
+    );
+    assert_eq!(Grammar::parse(grammar.source()).unwrap(), grammar);
+}
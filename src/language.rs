@@ -0,0 +1,474 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+use strum::{EnumIter, IntoEnumIterator};
+use whisper_rs::WhisperError;
+
+use crate::{transcode, transcript::Transcript, vad, Model, ModelError, TranscribeOptions};
+
+/// Language detected in some audio. See [`Model::detect_language`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectedLanguage {
+    /// Code of the most likely language, such as `"en"` or `"de"`.
+    pub language: String,
+    /// Probability whisper assigned to the language, from 0 to 1.
+    pub probability: f32,
+}
+
+/// Codes of the [`Language`]s, in the same order.
+const LANGUAGE_CODES: [&str; 100] = [
+    "en", "zh", "de", "es", "ru", "ko", "fr", "ja", "pt", "tr", "pl", "ca", "nl", "ar", "sv", "it",
+    "id", "hi", "fi", "vi", "he", "uk", "el", "ms", "cs", "ro", "da", "hu", "ta", "no", "th", "ur",
+    "hr", "bg", "lt", "la", "mi", "ml", "cy", "sk", "te", "fa", "lv", "bn", "sr", "az", "sl", "kn",
+    "et", "mk", "br", "eu", "is", "hy", "ne", "mn", "bs", "kk", "sq", "sw", "gl", "mr", "pa", "si",
+    "km", "sn", "yo", "so", "af", "oc", "ka", "be", "tg", "sd", "gu", "am", "yi", "lo", "uz", "fo",
+    "ht", "ps", "tk", "nn", "mt", "sa", "lb", "my", "bo", "tl", "mg", "as", "tt", "haw", "ln",
+    "ha", "ba", "jw", "su", "yue",
+];
+
+/// A language supported by whisper, in the order of whisper's language ids. Unlike a
+/// language code, a typo in one doesn't compile. Pass it to
+/// [`Model::transcribe_in`] or [`TranscribeOptions::with_language`].
+///
+/// ```
+/// use mutter::{Language, TranscribeOptions};
+///
+/// assert_eq!(Language::German.as_code(), "de");
+/// assert_eq!("de".parse::<Language>().unwrap(), Language::German);
+/// let options = TranscribeOptions::default().with_language(Language::German);
+/// assert_eq!(options.language.as_deref(), Some("de"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumIter)]
+pub enum Language {
+    /// English, `"en"`.
+    English,
+    /// Chinese, `"zh"`.
+    Chinese,
+    /// German, `"de"`.
+    German,
+    /// Spanish, `"es"`.
+    Spanish,
+    /// Russian, `"ru"`.
+    Russian,
+    /// Korean, `"ko"`.
+    Korean,
+    /// French, `"fr"`.
+    French,
+    /// Japanese, `"ja"`.
+    Japanese,
+    /// Portuguese, `"pt"`.
+    Portuguese,
+    /// Turkish, `"tr"`.
+    Turkish,
+    /// Polish, `"pl"`.
+    Polish,
+    /// Catalan, `"ca"`.
+    Catalan,
+    /// Dutch, `"nl"`.
+    Dutch,
+    /// Arabic, `"ar"`.
+    Arabic,
+    /// Swedish, `"sv"`.
+    Swedish,
+    /// Italian, `"it"`.
+    Italian,
+    /// Indonesian, `"id"`.
+    Indonesian,
+    /// Hindi, `"hi"`.
+    Hindi,
+    /// Finnish, `"fi"`.
+    Finnish,
+    /// Vietnamese, `"vi"`.
+    Vietnamese,
+    /// Hebrew, `"he"`.
+    Hebrew,
+    /// Ukrainian, `"uk"`.
+    Ukrainian,
+    /// Greek, `"el"`.
+    Greek,
+    /// Malay, `"ms"`.
+    Malay,
+    /// Czech, `"cs"`.
+    Czech,
+    /// Romanian, `"ro"`.
+    Romanian,
+    /// Danish, `"da"`.
+    Danish,
+    /// Hungarian, `"hu"`.
+    Hungarian,
+    /// Tamil, `"ta"`.
+    Tamil,
+    /// Norwegian, `"no"`.
+    Norwegian,
+    /// Thai, `"th"`.
+    Thai,
+    /// Urdu, `"ur"`.
+    Urdu,
+    /// Croatian, `"hr"`.
+    Croatian,
+    /// Bulgarian, `"bg"`.
+    Bulgarian,
+    /// Lithuanian, `"lt"`.
+    Lithuanian,
+    /// Latin, `"la"`.
+    Latin,
+    /// Maori, `"mi"`.
+    Maori,
+    /// Malayalam, `"ml"`.
+    Malayalam,
+    /// Welsh, `"cy"`.
+    Welsh,
+    /// Slovak, `"sk"`.
+    Slovak,
+    /// Telugu, `"te"`.
+    Telugu,
+    /// Persian, `"fa"`.
+    Persian,
+    /// Latvian, `"lv"`.
+    Latvian,
+    /// Bengali, `"bn"`.
+    Bengali,
+    /// Serbian, `"sr"`.
+    Serbian,
+    /// Azerbaijani, `"az"`.
+    Azerbaijani,
+    /// Slovenian, `"sl"`.
+    Slovenian,
+    /// Kannada, `"kn"`.
+    Kannada,
+    /// Estonian, `"et"`.
+    Estonian,
+    /// Macedonian, `"mk"`.
+    Macedonian,
+    /// Breton, `"br"`.
+    Breton,
+    /// Basque, `"eu"`.
+    Basque,
+    /// Icelandic, `"is"`.
+    Icelandic,
+    /// Armenian, `"hy"`.
+    Armenian,
+    /// Nepali, `"ne"`.
+    Nepali,
+    /// Mongolian, `"mn"`.
+    Mongolian,
+    /// Bosnian, `"bs"`.
+    Bosnian,
+    /// Kazakh, `"kk"`.
+    Kazakh,
+    /// Albanian, `"sq"`.
+    Albanian,
+    /// Swahili, `"sw"`.
+    Swahili,
+    /// Galician, `"gl"`.
+    Galician,
+    /// Marathi, `"mr"`.
+    Marathi,
+    /// Punjabi, `"pa"`.
+    Punjabi,
+    /// Sinhala, `"si"`.
+    Sinhala,
+    /// Khmer, `"km"`.
+    Khmer,
+    /// Shona, `"sn"`.
+    Shona,
+    /// Yoruba, `"yo"`.
+    Yoruba,
+    /// Somali, `"so"`.
+    Somali,
+    /// Afrikaans, `"af"`.
+    Afrikaans,
+    /// Occitan, `"oc"`.
+    Occitan,
+    /// Georgian, `"ka"`.
+    Georgian,
+    /// Belarusian, `"be"`.
+    Belarusian,
+    /// Tajik, `"tg"`.
+    Tajik,
+    /// Sindhi, `"sd"`.
+    Sindhi,
+    /// Gujarati, `"gu"`.
+    Gujarati,
+    /// Amharic, `"am"`.
+    Amharic,
+    /// Yiddish, `"yi"`.
+    Yiddish,
+    /// Lao, `"lo"`.
+    Lao,
+    /// Uzbek, `"uz"`.
+    Uzbek,
+    /// Faroese, `"fo"`.
+    Faroese,
+    /// Haitian Creole, `"ht"`.
+    HaitianCreole,
+    /// Pashto, `"ps"`.
+    Pashto,
+    /// Turkmen, `"tk"`.
+    Turkmen,
+    /// Nynorsk, `"nn"`.
+    Nynorsk,
+    /// Maltese, `"mt"`.
+    Maltese,
+    /// Sanskrit, `"sa"`.
+    Sanskrit,
+    /// Luxembourgish, `"lb"`.
+    Luxembourgish,
+    /// Myanmar, `"my"`.
+    Myanmar,
+    /// Tibetan, `"bo"`.
+    Tibetan,
+    /// Tagalog, `"tl"`.
+    Tagalog,
+    /// Malagasy, `"mg"`.
+    Malagasy,
+    /// Assamese, `"as"`.
+    Assamese,
+    /// Tatar, `"tt"`.
+    Tatar,
+    /// Hawaiian, `"haw"`.
+    Hawaiian,
+    /// Lingala, `"ln"`.
+    Lingala,
+    /// Hausa, `"ha"`.
+    Hausa,
+    /// Bashkir, `"ba"`.
+    Bashkir,
+    /// Javanese, `"jw"`.
+    Javanese,
+    /// Sundanese, `"su"`.
+    Sundanese,
+    /// Cantonese, `"yue"`.
+    Cantonese,
+}
+
+impl Language {
+    /// Code whisper knows the language by, such as `"en"` or `"de"`. See
+    /// [`Model::languages`].
+    #[must_use]
+    pub const fn as_code(self) -> &'static str {
+        LANGUAGE_CODES[self as usize]
+    }
+}
+
+impl Display for Language {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_code())
+    }
+}
+
+impl FromStr for Language {
+    type Err = ModelError;
+
+    /// Parses a language code, such as `"en"`.
+    ///
+    /// # Errors
+    /// - [`ModelError::InvalidLanguage`], if `code` isn't a language code supported by
+    ///   whisper.
+    fn from_str(code: &str) -> Result<Self, ModelError> {
+        Self::iter()
+            .find(|language| language.as_code() == code)
+            .ok_or_else(|| ModelError::InvalidLanguage(code.to_owned()))
+    }
+}
+
+impl From<Language> for String {
+    fn from(language: Language) -> Self {
+        language.as_code().to_owned()
+    }
+}
+
+impl Model {
+    /// Detects the language spoken in the first 30 seconds of the audio, given the
+    /// audio is an [f32] float array of codec `pcm_s16le` and in single-channel format.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to detect the language of. Must be a [f32] array.
+    /// - `threads`: Number of threads to use. `None` will use the number of cores from
+    ///   the `num_cpus` crate.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [`DetectedLanguage`], the most likely language and its probability.
+    pub fn detect_language(
+        &self,
+        audio: &[f32],
+        threads: Option<u16>,
+    ) -> Result<DetectedLanguage, ModelError> {
+        let threads = crate::thread_count(threads)?;
+        let audio = transcode::pad_to_min_length(audio);
+        let mut state = self.state(None)?;
+        state
+            .pcm_to_mel(&audio, threads)
+            .map_err(ModelError::WhisperError)?;
+        let probabilities = state
+            .lang_detect(0, threads)
+            .map_err(ModelError::WhisperError)?;
+        // whisper.cpp only fails to give a probability for every language if it failed
+        // outright, which `lang_detect` already reports as an error.
+        let (language, probability) = (0..)
+            .zip(probabilities)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .and_then(|(id, probability)| Some((whisper_rs::get_lang_str(id)?, probability)))
+            .ok_or(ModelError::WhisperError(WhisperError::GenericError(-1)))?;
+        let language = language.to_owned();
+        trace!("Detected language {language} with probability {probability}");
+        Ok(DetectedLanguage {
+            language,
+            probability,
+        })
+    }
+
+    /// Detects the language spoken in the audio like [`Model::detect_language`], but
+    /// only returns it if whisper is at least `min_probability` sure of it. Short or
+    /// ambiguous clips often fall below, e.g., 0.5, and can then be treated as unknown.
+    ///
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// The language code, or `None` if its probability is below `min_probability`.
+    pub fn detect_language_above(
+        &self,
+        audio: &[f32],
+        min_probability: f32,
+        threads: Option<u16>,
+    ) -> Result<Option<String>, ModelError> {
+        let detected = self.detect_language(audio, threads)?;
+        Ok((detected.probability >= min_probability).then_some(detected.language))
+    }
+
+    /// Transcribes audio like [`Model::transcribe`], in the given [`Language`] rather
+    /// than `options.language`.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `language`: [`Language`] spoken in the audio, or `None` to detect it.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_in(
+        &self,
+        audio: impl AsRef<[u8]>,
+        language: Option<Language>,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        let options = TranscribeOptions {
+            language: language.map(String::from),
+            ..options.clone()
+        };
+        self.transcribe(audio, &options)
+    }
+
+    /// Transcribes code-switched audio, in which speakers switch between languages,
+    /// given the audio is a byte array of a file. The audio is split into pieces of
+    /// at most `piece_length`, each cut at a quiet moment, and the language of each
+    /// piece is detected before it is transcribed in that language. See
+    /// [`Model::transcribe_pcm_code_switched`].
+    ///
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::InvalidRange`], if `piece_length` is zero.
+    /// # Returns
+    /// [Transcript], with the [`Utterance::language`](crate::transcript::Utterance::language)
+    /// of every utterance.
+    pub fn transcribe_code_switched(
+        &self,
+        audio: impl AsRef<[u8]>,
+        piece_length: Duration,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        trace!("Decoding audio.");
+        let decode_start = Instant::now();
+        let decoded = transcode::decode(audio.as_ref().to_vec())?;
+        let decode_time = decode_start.elapsed();
+        let mut transcript =
+            self.transcribe_pcm_code_switched(&decoded.samples, piece_length, options)?;
+        transcript.timings.decode = decode_time;
+        decoded.annotate(&mut transcript);
+        Ok(transcript)
+    }
+
+    /// Transcribes code-switched audio, given the audio is an [f32] float array of
+    /// codec `pcm_s16le` and in single-channel format.
+    ///
+    /// Shorter pieces follow the switches between languages more closely, but give
+    /// whisper less to detect the language from; around 10 seconds works well for
+    /// conversations. Pieces don't share context, as text in one language is a poor
+    /// prompt for another. `options.language` is ignored, and needs a multilingual
+    /// model.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. Must be a [f32] array.
+    /// - `piece_length`: Longest piece of audio to detect the language of.
+    /// - `options`: [`TranscribeOptions`] to transcribe every piece with.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::InvalidRange`], if `piece_length` is zero.
+    /// # Returns
+    /// [Transcript], with the [`Utterance::language`](crate::transcript::Utterance::language)
+    /// of every utterance.
+    pub fn transcribe_pcm_code_switched(
+        &self,
+        audio: &[f32],
+        piece_length: Duration,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        if piece_length.is_zero() {
+            return Err(ModelError::InvalidRange);
+        }
+        let mut piece_options = TranscribeOptions {
+            reset_context_every: None,
+            chunk_longer_than: None,
+            ..options.clone()
+        };
+        let mut transcript = Transcript {
+            word_utterances: options.word_timestamps.then(Vec::new),
+            audio_duration: Some(Duration::ZERO),
+            ..Transcript::default()
+        };
+        let mut start = 0;
+        for end in vad::split_points(audio, piece_length)
+            .into_iter()
+            .chain([audio.len()])
+        {
+            let piece = &audio[start..end];
+            let language = self.detect_language(piece, options.threads)?.language;
+            piece_options.language = Some(language.clone());
+            let first_utterance = transcript.utterances.len();
+            self.transcribe_piece(
+                piece,
+                start,
+                false,
+                &mut piece_options,
+                &mut transcript,
+                None,
+            )?;
+            for utterance in &mut transcript.utterances[first_utterance..] {
+                utterance.language = Some(language.clone());
+            }
+            start = end;
+        }
+        Ok(transcript)
+    }
+}
+
+#[test]
+fn test_language_codes() {
+    assert_eq!(Language::English.as_code(), "en");
+    assert_eq!(Language::HaitianCreole.as_code(), "ht");
+    assert_eq!(Language::Cantonese.to_string(), "yue");
+    assert_eq!(String::from(Language::Japanese), "ja");
+    assert_eq!("fr".parse::<Language>().unwrap(), Language::French);
+    assert!(matches!(
+        "english".parse::<Language>(),
+        Err(ModelError::InvalidLanguage(code)) if code == "english"
+    ));
+    assert_eq!(Language::iter().count(), 100);
+}
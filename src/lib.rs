@@ -40,23 +40,85 @@
 //! symphonia-wav = ["rodio/symphonia-wav"]
 //! ```
 //!
-use std::{fmt::Display, time::Instant};
+//! # Tracing
+//!
+//! Enable the `tracing` feature to get [tracing](https://docs.rs/tracing) spans around
+//! downloading, decoding and inference, with fields such as the number of samples.
+//!
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{ErrorKind, Read},
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
 
-use log::{info, trace};
+use log::{debug, info, trace, warn};
+use pool::{State, StatePool};
 use strum::EnumIter;
-use transcript::{Transcript, Utterance};
-use whisper_rs::{
-    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperError,
-};
+use transcript::{Benchmark, Timings, TokenAssembler, Transcript, Utterance};
+use vad::SpeechRanges;
+use whisper_rs::{FullParams, WhisperContext, WhisperError};
 
+mod align;
+mod batch;
+mod cache;
+mod chunked;
+pub mod formatter;
+pub mod grammar;
+mod language;
+pub mod options;
+mod pool;
+mod stl;
+mod streaming;
 mod tests;
 mod transcode;
 pub mod transcript;
+pub mod vad;
+
+pub use chunked::ChunkedTranscript;
+pub use formatter::TranscriptFormatter;
+pub use grammar::Grammar;
+pub use language::{DetectedLanguage, Language};
+pub use options::{DecodeOptions, Sampling, TranscribeOptions};
+pub use pool::ModelPool;
+pub use stl::StlFrameRate;
+pub use streaming::TranscriptStream;
+pub use transcode::{
+    clipping_ratio, probe_duration, PcmFormat, PreparedAudio, CLIPPING_WARN_RATIO, SAMPLE_RATE,
+};
+pub use ureq::{Agent, AgentBuilder};
+pub use vad::EnergyVad;
+pub use whisper_rs::WhisperContextParameters;
 
 /// Model struct. Can be constructed with [`Model::new`] or [`Model::download`].
 /// Contains the Whisper model and its context.
+///
+/// # Concurrency
+///
+/// `Model` is [`Send`] and [`Sync`]: share one between threads or request handlers
+/// with an [`Arc<Model>`](std::sync::Arc), and transcribe from all of them at once.
+/// The loaded weights are read-only; every transcription creates its own whisper
+/// state, which holds all the mutable data, and drops it when done.
+///
+/// That state is large, though: from tens of megabytes for [`ModelType::TinyEn`] to
+/// several hundred for [`ModelType::LargeV3`], on top of the model itself. Running
+/// many transcriptions at once can exhaust memory, or GPU memory with the `cuda`
+/// feature, so limit how many run concurrently, e.g. with a semaphore, or use a
+/// [`ModelPool`], which allocates a fixed number of states once and reuses them.
+///
+/// # Settings
+///
+/// Only the [`WhisperContextParameters`] a model is loaded with, such as whether to
+/// use the GPU, are fixed for its lifetime, along with what the model file itself
+/// determines, like its mel filters. Every [`TranscribeOptions`] field applies to a
+/// single call, so changing options never requires loading the model again.
 pub struct Model {
     context: WhisperContext,
+    /// Identifies the model in [`Model::transcribe_cached`]'s cache: the path it was
+    /// loaded from, or the [`ModelType::file_name`] of a downloaded model.
+    name: String,
 }
 
 impl Model {
@@ -67,6 +129,27 @@ impl Model {
     /// # Errors
     /// - [`WhisperError`]
     pub fn new(path: &str) -> Result<Self, WhisperError> {
+        Self::new_with_context_params(path, WhisperContextParameters::default())
+    }
+
+    /// Creates a new model from a model path, with the given whisper context
+    /// parameters. Use this for settings fixed at load time, such as whether to use
+    /// the GPU, that mutter doesn't wrap itself. Changing them means loading the model
+    /// again; everything in [`TranscribeOptions`] can change from call to call.
+    ///
+    /// Flash attention isn't available yet: the bundled whisper.cpp (via whisper-rs
+    /// 0.11) predates it. It will become reachable through these parameters once
+    /// whisper-rs is updated.
+    /// # Arguments
+    /// - `path`: Path to the model.
+    /// - `params`: [`WhisperContextParameters`] to load the model with.
+    /// # Errors
+    /// - [`WhisperError`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(params)))]
+    pub fn new_with_context_params(
+        path: &str,
+        params: WhisperContextParameters,
+    ) -> Result<Self, WhisperError> {
         trace!("Loading model {}", path);
         // Sanity check - make sure the path exists
         let path_converted = std::path::Path::new(path);
@@ -74,15 +157,49 @@ impl Model {
             return Err(WhisperError::InitError);
         }
 
-        let params: WhisperContextParameters = WhisperContextParameters::default();
         Ok({
             Self {
                 context: WhisperContext::new_with_params(path, params)?,
+                name: path.to_owned(),
             }
         })
     }
 
+    /// Creates a new model from a model path like [`Model::new_with_context_params`],
+    /// but if loading it onto the GPU fails, e.g. because the GPU is out of memory,
+    /// logs a warning and loads it onto the CPU instead. Transcribing on the CPU is
+    /// much slower, but better than failing on a busy, shared GPU.
+    ///
+    /// Only loading the model falls back. The state whisper allocates for each
+    /// transcription can still fail to fit later, which is reported as a
+    /// [`ModelError::WhisperError`] by the transcribing method.
+    /// # Arguments
+    /// - `path`: Path to the model.
+    /// - `params`: [`WhisperContextParameters`] to load the model with. If `use_gpu`
+    ///   is `false`, there is nothing to fall back to, and this is the same as
+    ///   [`Model::new_with_context_params`].
+    /// # Errors
+    /// - [`WhisperError`], if the model can't be loaded onto the CPU either.
+    pub fn new_with_cpu_fallback(
+        path: &str,
+        params: WhisperContextParameters,
+    ) -> Result<Self, WhisperError> {
+        // The parameters aren't `Copy`, and are gone once the first attempt is made.
+        let use_gpu = params.use_gpu;
+        match Self::new_with_context_params(path, params) {
+            Err(e) if use_gpu && std::path::Path::new(path).exists() => {
+                warn!("Couldn't load model {path} onto the GPU ({e:?}), falling back to the CPU");
+                let mut cpu_params = WhisperContextParameters::default();
+                cpu_params.use_gpu(false);
+                Self::new_with_context_params(path, cpu_params)
+            }
+            result => result,
+        }
+    }
+
     /// Creates a new model and downloads the specified model type from huggingface.
+    /// Uses ureq's default timeouts, which don't limit how long a stalled download can
+    /// hang; use [`Model::download_with_agent`] to set your own.
     /// # Arguments
     /// - `model`: [`ModelType`].
     /// # Errors
@@ -93,12 +210,118 @@ impl Model {
     /// # Panics
     /// This function shouldn't panic, but may due to the underlying -sys bindings.
     /// It shouldn't panic within _this_ crate.
-
     pub fn download(model: &ModelType) -> Result<Self, ModelError> {
+        Self::download_with_agent(model, &ureq::agent())
+    }
+
+    /// Creates a new model and downloads the specified model type from huggingface,
+    /// using the given [`Agent`], e.g. to set timeouts or a proxy.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use mutter::{AgentBuilder, Model, ModelType};
+    ///
+    /// let agent = AgentBuilder::new()
+    ///     .timeout_connect(Duration::from_secs(10))
+    ///     .timeout_read(Duration::from_secs(30))
+    ///     .build();
+    /// let model = Model::download_with_agent(&ModelType::BaseEn, &agent).unwrap();
+    /// ```
+    /// # Arguments
+    /// - `model`: [`ModelType`].
+    /// - `agent`: [`Agent`] to download with.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::WhisperError`],
+    ///     - [`ModelError::DownloadError`], including if a timeout is reached.
+    ///     - [`ModelError::IoError`], including if the read timeout is reached.
+    /// # Panics
+    /// This function shouldn't panic, but may due to the underlying -sys bindings.
+    /// It shouldn't panic within _this_ crate.
+    pub fn download_with_agent(model: &ModelType, agent: &Agent) -> Result<Self, ModelError> {
+        Self::download_cancellable(model, agent, &AtomicBool::new(false), None)
+    }
+
+    /// Creates a new model and downloads the specified model type from huggingface,
+    /// using the given [`Agent`], like [`Model::download_with_agent`], but the download
+    /// can be cancelled, or bounded by a deadline. Either is checked every time a block
+    /// of the model has been received, and the deadline also bounds connecting and
+    /// every read, so a stalled connection can't outlast it.
+    ///
+    /// ```no_run
+    /// use std::{
+    ///     sync::atomic::AtomicBool,
+    ///     time::{Duration, Instant},
+    /// };
+    /// use mutter::{Agent, Model, ModelType};
+    ///
+    /// let abort = AtomicBool::new(false);
+    /// let deadline = Instant::now() + Duration::from_secs(120);
+    /// let model =
+    ///     Model::download_cancellable(&ModelType::BaseEn, &Agent::new(), &abort, Some(deadline));
+    /// ```
+    /// # Arguments
+    /// - `model`: [`ModelType`].
+    /// - `agent`: [`Agent`] to download with.
+    /// - `abort`: Set this to `true`, from any thread, to cancel the download.
+    /// - `deadline`: When to give up on the download, if it hasn't finished by then.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::Cancelled`], if `abort` was set or `deadline` was reached.
+    ///       Whatever was downloaded so far is dropped.
+    ///     - [`ModelError::WhisperError`],
+    ///     - [`ModelError::DownloadError`],
+    ///     - [`ModelError::IoError`],
+    /// # Panics
+    /// This function shouldn't panic, but may due to the underlying -sys bindings.
+    /// It shouldn't panic within _this_ crate.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(model = %model)))]
+    pub fn download_cancellable(
+        model: &ModelType,
+        agent: &Agent,
+        abort: &AtomicBool,
+        deadline: Option<Instant>,
+    ) -> Result<Self, ModelError> {
+        let bytes = Self::download_bytes(model, agent, abort, deadline)?;
+        let params: WhisperContextParameters = WhisperContextParameters::default();
+
+        Ok({
+            Self {
+                context: WhisperContext::new_from_buffer_with_params(&bytes, params)
+                    .map_err(ModelError::WhisperError)?,
+                name: model.file_name().to_owned(),
+            }
+        })
+    }
+
+    /// Downloads the specified model type from huggingface, without loading it. Stops
+    /// with [`ModelError::Cancelled`] once `abort` is set or `deadline` is reached.
+    fn download_bytes(
+        model: &ModelType,
+        agent: &Agent,
+        abort: &AtomicBool,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<u8>, ModelError> {
+        let cancelled = || {
+            abort.load(Ordering::Relaxed)
+                || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+        };
+        if cancelled() {
+            return Err(ModelError::Cancelled);
+        }
+
         trace!("Downloading model {}", model);
-        let resp = ureq::get(&model.to_string())
-            .call()
-            .map_err(|e| ModelError::DownloadError(Box::new(e)))?;
+        let mut request = agent.get(&model.to_string());
+        if let Some(deadline) = deadline {
+            request = request.timeout(deadline.saturating_duration_since(Instant::now()));
+        }
+        let resp = request.call().map_err(|e| {
+            if cancelled() {
+                ModelError::Cancelled
+            } else {
+                ModelError::DownloadError(Box::new(e))
+            }
+        })?;
         assert!(resp.has("Content-Length"));
         let len: usize = resp
             .header("Content-Length")
@@ -107,19 +330,75 @@ impl Model {
             .unwrap_or_default();
         trace!("Model length: {}", len);
         let mut bytes: Vec<u8> = Vec::with_capacity(len);
-        resp.into_reader()
-            .read_to_end(&mut bytes)
-            .map_err(ModelError::IoError)?;
+        let mut reader = resp.into_reader();
+        let mut block = vec![0; DOWNLOAD_BLOCK_LEN];
+        loop {
+            if cancelled() {
+                info!("Cancelled downloading model {model}");
+                return Err(ModelError::Cancelled);
+            }
+            match reader.read(&mut block) {
+                Ok(0) => break,
+                Ok(read) => bytes.extend_from_slice(&block[..read]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                // The deadline's timeout cut a read short.
+                Err(_) if cancelled() => return Err(ModelError::Cancelled),
+                Err(e) => return Err(ModelError::IoError(e)),
+            }
+        }
         assert_eq!(bytes.len(), len);
         info!("Downloaded model: {}", model);
-        let params: WhisperContextParameters = WhisperContextParameters::default();
+        Ok(bytes)
+    }
 
-        Ok({
-            Self {
-                context: WhisperContext::new_from_buffer_with_params(&bytes, params)
-                    .map_err(ModelError::WhisperError)?,
-            }
-        })
+    /// Number of mel bands the model's spectrogram has: 128 for Large V3, 80 for every
+    /// other model. whisper.cpp computes the spectrogram with the mel filters stored
+    /// in the model file, so it always matches the model; mutter only hands it 16 kHz
+    /// samples. Compare with [`ModelType::n_mels`] to check a model file is what you
+    /// expect.
+    #[must_use]
+    pub fn n_mels(&self) -> usize {
+        usize::try_from(self.context.model_n_mels()).unwrap_or_default()
+    }
+
+    /// Largest audio context the model's encoder supports: 1500 for every released
+    /// model, covering 30 seconds of audio. [`TranscribeOptions::audio_ctx`] should be
+    /// no larger.
+    #[must_use]
+    pub fn max_audio_ctx(&self) -> usize {
+        usize::try_from(self.context.model_n_audio_ctx()).unwrap_or_default()
+    }
+
+    /// Largest number of tokens the model's decoder attends to, including the prompt:
+    /// 448 for every released model.
+    #[must_use]
+    pub fn n_text_ctx(&self) -> usize {
+        usize::try_from(self.context.model_n_text_ctx()).unwrap_or_default()
+    }
+
+    /// Lists the codes of all languages supported by whisper, such as `"en"` or `"de"`.
+    /// English-only models (e.g. [`ModelType::BaseEn`]) only support `"en"`.
+    #[must_use]
+    pub fn languages() -> Vec<&'static str> {
+        (0..=whisper_rs::get_lang_max_id())
+            .filter_map(whisper_rs::get_lang_str)
+            .collect()
+    }
+
+    /// Whether the model can translate, i.e. is multilingual. English-only models,
+    /// such as [`ModelType::BaseEn`], can't; transcribing with
+    /// [`TranscribeOptions::translate`] set fails with
+    /// [`ModelError::TranslationUnsupported`] on them.
+    #[must_use]
+    pub fn supports_translation(&self) -> bool {
+        self.context.is_multilingual()
+    }
+
+    /// Checks whether `code` is a language code supported by whisper. See
+    /// [`Model::languages`].
+    #[must_use]
+    pub fn is_valid_language(code: &str) -> bool {
+        Self::languages().contains(&code)
     }
 
     /// Transcribes audio to text, given the audio is a byte array of a file.
@@ -133,7 +412,7 @@ impl Model {
     /// - `initial_prompt`: Optinal initial prompt to whisper model.
     /// - `language`: Optinal language setting for whisper model.
     /// - `threads`: Number of threads to use. `None` will use the number of cores from
-    /// the `num_cpus` crate.
+    ///   the `num_cpus` crate.
     /// # Errors
     /// - [`ModelError`]
     /// # Returns
@@ -147,22 +426,290 @@ impl Model {
         language: Option<&str>,
         threads: Option<u16>,
     ) -> Result<Transcript, ModelError> {
-        trace!("Decoding audio.");
-        let samples = transcode::decode(audio.as_ref().to_vec())?;
-        trace!("Transcribing audio.");
-        self.transcribe_pcm_s16le(
-            &samples,
+        let options = TranscribeOptions {
             translate,
             word_timestamps,
-            initial_prompt,
-            language,
+            initial_prompt: initial_prompt.map(ToOwned::to_owned),
+            language: language.map(ToOwned::to_owned),
             threads,
-        )
+            ..TranscribeOptions::default()
+        };
+        self.transcribe(audio, &options)
+    }
+
+    /// Transcribes audio to text, given the audio is a byte array of a file, using
+    /// the given [`TranscribeOptions`].
+    /// Supported codecs: MP3 (Symphonia), WAV (Hound), OGG Vorbis (lewton),
+    /// FLAC (claxon).
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe(
+        &self,
+        audio: impl AsRef<[u8]>,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        self.transcribe_with_samples(audio, options)
+            .map(|(transcript, _)| transcript)
+    }
+
+    /// Transcribes audio to text like [`Model::transcribe`], and also returns the
+    /// decoded samples, so further analysis of the same audio, such as voice activity
+    /// detection, doesn't have to decode it again.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [Transcript], and the audio as whisper heard it: single-channel [f32] samples at
+    /// [`SAMPLE_RATE`], between -1.0 and 1.0, after the speech filters described in
+    /// the README.
+    pub fn transcribe_with_samples(
+        &self,
+        audio: impl AsRef<[u8]>,
+        options: &TranscribeOptions,
+    ) -> Result<(Transcript, Vec<f32>), ModelError> {
+        self.transcribe_with_samples_using(audio, options, None)
+    }
+
+    /// [`Model::transcribe_with_samples`], taking whisper states from `states` if given.
+    pub(crate) fn transcribe_with_samples_using<'a>(
+        &'a self,
+        audio: impl AsRef<[u8]>,
+        options: &TranscribeOptions,
+        states: Option<&StatePool<'a>>,
+    ) -> Result<(Transcript, Vec<f32>), ModelError> {
+        trace!("Decoding audio.");
+        let decode_start = Instant::now();
+        let decoded =
+            transcode::decode_up_to(audio.as_ref().to_vec(), options.max_transcribe_duration)?;
+        let decode_time = decode_start.elapsed();
+        trace!("Transcribing audio.");
+        let mut transcript = self.transcribe_pcm_using(&decoded.samples, options, states)?;
+        transcript.timings.decode = decode_time;
+        decoded.annotate(&mut transcript);
+        Ok((transcript, decoded.samples))
+    }
+
+    /// Decodes audio once, so it can be transcribed any number of times with
+    /// [`Model::transcribe_prepared`] without decoding it again.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to decode. An array of bytes.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [`PreparedAudio`]
+    pub fn prepare_audio(audio: impl AsRef<[u8]>) -> Result<PreparedAudio, ModelError> {
+        trace!("Decoding audio.");
+        let decoded = transcode::decode(audio.as_ref().to_vec())?;
+        Ok(PreparedAudio { decoded })
+    }
+
+    /// Transcribes audio decoded by [`Model::prepare_audio`], like [`Model::transcribe`]
+    /// but without decoding it again. The decode time in [`Transcript::timings`] is
+    /// zero.
+    ///
+    /// # Arguments
+    /// - `audio`: [`PreparedAudio`] to transcribe.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_prepared(
+        &self,
+        audio: &PreparedAudio,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        let mut transcript = self.transcribe_pcm(&audio.decoded.samples, options)?;
+        audio.decoded.annotate(&mut transcript);
+        Ok(transcript)
+    }
+
+    /// Transcribes audio to text, given the audio is a byte array of a file, using the
+    /// given [`DecodeOptions`] to preprocess the decoded audio, such as to denoise it.
+    /// See [`Model::transcribe`].
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `decode_options`: [`DecodeOptions`] to decode with.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_with_decode_options(
+        &self,
+        audio: impl AsRef<[u8]>,
+        decode_options: &DecodeOptions,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        trace!("Decoding audio.");
+        let decode_start = Instant::now();
+        let decoded = transcode::decode_with(
+            audio.as_ref().to_vec(),
+            decode_options,
+            options.max_transcribe_duration,
+        )?;
+        let decode_time = decode_start.elapsed();
+        trace!("Transcribing audio.");
+        let mut transcript = self.transcribe_pcm(&decoded.samples, options)?;
+        transcript.timings.decode = decode_time;
+        decoded.annotate(&mut transcript);
+        Ok(transcript)
+    }
+
+    /// Transcribes audio to text, given the audio is an array of interleaved [f32]
+    /// samples with any number of channels and any sample rate, e.g. the output of a
+    /// media player's decoder. The audio is resampled and downmixed the same way as in
+    /// [`Model::transcribe`].
+    ///
+    /// # Arguments
+    /// - `samples`: Interleaved samples, between -1.0 and 1.0.
+    /// - `channels`: Number of channels in `samples`.
+    /// - `sample_rate`: Sample rate of `samples`, in Hz.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::UnsupportedFormat`], if `channels` or `sample_rate` is zero.
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_interleaved(
+        &self,
+        samples: &[f32],
+        channels: u16,
+        sample_rate: u32,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        trace!("Resampling {channels} channel audio from {sample_rate} Hz.");
+        let decode_start = Instant::now();
+        let decoded = transcode::decode_interleaved(samples.to_vec(), channels, sample_rate)?;
+        let decode_time = decode_start.elapsed();
+        let mut transcript = self.transcribe_pcm(&decoded.samples, options)?;
+        transcript.timings.decode = decode_time;
+        decoded.annotate(&mut transcript);
+        Ok(transcript)
+    }
+
+    /// Transcribes raw, headerless PCM audio, such as the `s16le` dumps of capture
+    /// hardware, which can't be decoded like a file as it has no header saying how to
+    /// interpret it. The audio is resampled and downmixed the same way as in
+    /// [`Model::transcribe`].
+    ///
+    /// # Arguments
+    /// - `audio`: Raw PCM bytes, with the samples of every channel interleaved.
+    /// - `sample_rate`: Sample rate of `audio`, in Hz.
+    /// - `channels`: Number of channels in `audio`.
+    /// - `format`: [`PcmFormat`] of each sample.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::UnsupportedFormat`], if `channels` or `sample_rate` is zero.
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_raw_pcm(
+        &self,
+        audio: impl AsRef<[u8]>,
+        sample_rate: u32,
+        channels: u16,
+        format: PcmFormat,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        trace!("Converting {channels} channel {format:?} audio from {sample_rate} Hz.");
+        let decode_start = Instant::now();
+        let decoded = transcode::decode_raw_pcm(audio.as_ref(), format, channels, sample_rate)?;
+        let decode_time = decode_start.elapsed();
+        let mut transcript = self.transcribe_pcm(&decoded.samples, options)?;
+        transcript.timings.decode = decode_time;
+        decoded.annotate(&mut transcript);
+        Ok(transcript)
+    }
+
+    /// Transcribes single-channel [f32] audio at any sample rate, resampling it to
+    /// [`SAMPLE_RATE`] first unless it's already at that rate.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe, between -1.0 and 1.0.
+    /// - `sample_rate`: Sample rate of `audio`, in Hz.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::UnsupportedFormat`], if `sample_rate` is zero.
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_pcm_at(
+        &self,
+        audio: &[f32],
+        sample_rate: u32,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        if sample_rate == SAMPLE_RATE {
+            return self.transcribe_pcm(audio, options);
+        }
+        self.transcribe_interleaved(audio, 1, sample_rate, options)
+    }
+
+    /// Transcribes only part of the audio, between `start` and `end`, given the audio
+    /// is a byte array of a file. The whole file is decoded, but only the requested
+    /// range is fed to whisper. Timestamps in the returned [Transcript] are relative to
+    /// the start of the file, not the start of the range, and so, like the clipping
+    /// ratio and source format, its `audio_duration` is that of the whole file.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `start`: Start of the range to transcribe.
+    /// - `end`: End of the range to transcribe. Clamped to the length of the audio.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::InvalidRange`], if `start` is not before `end`, or is past
+    ///       the end of the audio.
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_range(
+        &self,
+        audio: impl AsRef<[u8]>,
+        start: Duration,
+        end: Duration,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        trace!("Decoding audio.");
+        let decode_start = Instant::now();
+        let decoded = transcode::decode(audio.as_ref().to_vec())?;
+        let decode_time = decode_start.elapsed();
+        let first = transcode::duration_to_samples(start);
+        let last = transcode::duration_to_samples(end).min(decoded.samples.len());
+        if start >= end || first >= last {
+            return Err(ModelError::InvalidRange);
+        }
+        trace!("Transcribing samples {first}..{last}.");
+        let mut transcript = self.transcribe_pcm(&decoded.samples[first..last], options)?;
+        transcript.offset_by(transcript::duration_to_raw(start));
+        transcript.timings.decode = decode_time;
+        decoded.annotate(&mut transcript);
+        Ok(transcript)
     }
 
     /// Transcribes audio to text, given the audio is an [f32] float array of codec
     /// `pcm_s16le` and in single-channel format.
     ///
+    /// Despite the name, the samples are floats: 16 kHz `pcm_s16le` audio converted to
+    /// [f32] between -1.0 and 1.0. If you have the [i16] samples themselves, use
+    /// [`Model::transcribe_pcm_i16`].
+    ///
+    /// The sample rate must be [`SAMPLE_RATE`]. Nothing can check this for sure, and
+    /// audio at any other rate gives wrong text and timestamps without an error; use
+    /// [`Model::transcribe_pcm_at`] to have it resampled. With debug logging enabled, a
+    /// message is logged if the audio sounds like it's at another rate.
+    ///
     /// You probably want to use [`Model::transcribe_audio`] instead, unless you've already
     /// converted it into the correct format.
     ///
@@ -173,6 +720,7 @@ impl Model {
     /// - `initial_prompt`: Optinal initial prompt to whisper model.
     /// - `language`: Optinal language setting for whisper model.
     /// - `threads`: Number of threads to use. `None` will use the number of cores from
+    ///   the `num_cpus` crate.
     ///
     /// # Errors
     /// - [`ModelError`]
@@ -188,44 +736,267 @@ impl Model {
         initial_prompt: Option<&str>,
         language: Option<&str>,
         threads: Option<u16>,
+    ) -> Result<Transcript, ModelError> {
+        let options = TranscribeOptions {
+            translate,
+            word_timestamps,
+            initial_prompt: initial_prompt.map(ToOwned::to_owned),
+            language: language.map(ToOwned::to_owned),
+            threads,
+            ..TranscribeOptions::default()
+        };
+        transcode::check_sample_rate(audio);
+        self.transcribe_pcm(audio, &options)
+    }
+
+    /// Transcribes audio to text, given the audio is an [f32] float array of codec
+    /// `pcm_s16le` and in single-channel format, using the given [`TranscribeOptions`].
+    /// The samples must be between -1.0 and 1.0; for [i16] samples, use
+    /// [`Model::transcribe_pcm_i16`]. The sample rate must be [`SAMPLE_RATE`]; for
+    /// other rates, use [`Model::transcribe_pcm_at`].
+    ///
+    /// You probably want to use [`Model::transcribe`] instead, unless you've already
+    /// converted it into the correct format.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. Must be a [f32] array.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    ///
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::InvalidLanguage`], if the language isn't supported by whisper.
+    ///     - [`ModelError::InvalidThreadCount`], if `threads` is `Some(0)`.
+    ///     - [`ModelError::TranslationUnsupported`], if `translate` is set on an
+    ///       English-only model.
+    /// # Panics
+    /// This function shouldn't panic, but may due to the underlying -sys c bindings.
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_pcm(
+        &self,
+        audio: &[f32],
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        self.transcribe_pcm_using(audio, options, None)
+    }
+
+    /// [`Model::transcribe_pcm`], taking whisper states from `states` if given.
+    pub(crate) fn transcribe_pcm_using<'a>(
+        &'a self,
+        audio: &[f32],
+        options: &TranscribeOptions,
+        states: Option<&StatePool<'a>>,
     ) -> Result<Transcript, ModelError> {
         trace!(
-            "Transcribing audio: {} with translate: {translate} and timestamps: {word_timestamps}",
+            "Transcribing audio: {} with options: {options:?}",
             audio.len()
         );
+        let audio = options.limit(audio);
+        if let Some(piece_length) = options.reset_context_every {
+            return self.transcribe_pcm_in_pieces(audio, piece_length, false, options, states);
+        }
+        if let Some(piece_length) = options.chunk_longer_than {
+            if audio.len() > transcode::duration_to_samples(piece_length) {
+                let carry_context = !options.no_context;
+                return self.transcribe_pcm_in_pieces(
+                    audio,
+                    piece_length,
+                    carry_context,
+                    options,
+                    states,
+                );
+            }
+        }
+        let params = self.full_params(options)?;
+        self.run_full(params, audio, options, states)
+    }
 
-        let mut params = FullParams::new(SamplingStrategy::BeamSearch {
-            beam_size: 5,
-            patience: 1.0,
-        });
+    /// Transcribes audio to text, given the audio is an [i16] array of 16 kHz,
+    /// single-channel `pcm_s16le` samples, using the given [`TranscribeOptions`].
+    /// The samples are converted to floats and transcribed with
+    /// [`Model::transcribe_pcm`].
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. Must be an [i16] array.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    ///
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_pcm_i16(
+        &self,
+        audio: &[i16],
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        let decode_start = Instant::now();
+        let mut samples = vec![0.0f32; audio.len()];
+        whisper_rs::convert_integer_to_float_audio(audio, &mut samples)
+            .map_err(ModelError::WhisperError)?;
+        let decode_time = decode_start.elapsed();
+        transcode::check_sample_rate(&samples);
+        let mut transcript = self.transcribe_pcm(&samples, options)?;
+        transcript.timings.decode = decode_time;
+        Ok(transcript)
+    }
+
+    /// Runs whisper over the audio without extracting any text, to measure inference
+    /// alone, given the audio is an [f32] float array of codec `pcm_s16le` and in
+    /// single-channel format. Useful for benchmarks, where building a [Transcript]
+    /// would skew the results on short clips.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to run whisper over. An array of [f32] samples.
+    /// - `options`: [`TranscribeOptions`] to run whisper with.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [`Benchmark`]
+    pub fn benchmark(
+        &self,
+        audio: &[f32],
+        options: &TranscribeOptions,
+    ) -> Result<Benchmark, ModelError> {
+        let params = self.full_params(options)?;
+        let st = Instant::now();
+        let (state, _) = self.infer(params, audio, options, None)?;
+        let inference = st.elapsed();
+        let segments = state.full_n_segments().map_err(ModelError::WhisperError)?;
+        Ok(Benchmark {
+            inference,
+            segments: usize::try_from(segments).unwrap_or_default(),
+            audio_duration: transcode::samples_to_duration(audio.len()),
+        })
+    }
 
-        if let Some(prompt) = initial_prompt {
+    /// Roughly estimates how long transcribing `audio_len` of audio with `model` will
+    /// take, e.g. to show a progress bar with a meaningful total. The estimate assumes
+    /// a recent desktop CPU with a handful of threads and no GPU, and can easily be off
+    /// by a factor of two or more on other hardware. For a better estimate on the
+    /// hardware at hand, time a short clip with [`Model::benchmark`] and use
+    /// [`Benchmark::estimate_duration`].
+    /// # Arguments
+    /// - `audio_len`: Duration of the audio to transcribe.
+    /// - `model`: [`ModelType`] to transcribe with.
+    #[must_use]
+    pub fn estimate_duration(audio_len: Duration, model: &ModelType) -> Duration {
+        audio_len.mul_f32(model.typical_real_time_factor())
+    }
+
+    /// Builds the whisper parameters for the given options.
+    fn full_params<'a>(
+        &self,
+        options: &'a TranscribeOptions,
+    ) -> Result<FullParams<'a, 'a>, ModelError> {
+        if let Some(language) = &options.language {
+            if language != "auto" && !Self::is_valid_language(language) {
+                return Err(ModelError::InvalidLanguage(language.clone()));
+            }
+        }
+        // English-only models ignore `translate`, leaving the text untranslated.
+        if options.translate && !self.supports_translation() {
+            return Err(ModelError::TranslationUnsupported);
+        }
+
+        let mut params = FullParams::new(options.sampling.to_strategy());
+
+        if let Some(prompt) = &options.initial_prompt {
             params.set_initial_prompt(prompt);
         }
 
-        params.set_language(language);
+        params.set_language(options.language.as_deref());
 
-        params.set_translate(translate);
+        params.set_translate(options.translate);
         params.set_print_special(false);
         params.set_print_progress(false);
         params.set_print_realtime(false);
         params.set_print_timestamps(false);
-        params.set_token_timestamps(word_timestamps);
+        params.set_token_timestamps(options.word_timestamps);
         params.set_split_on_word(true);
+        params.set_single_segment(options.single_segment);
+        params.set_no_context(options.no_context);
+        params.set_suppress_blank(options.suppress_blank);
+        params.set_suppress_non_speech_tokens(options.suppress_non_speech_tokens);
+        params.set_temperature(options.temperature);
+        params.set_temperature_inc(options.temperature_inc);
+        params.set_entropy_thold(options.entropy_thold);
+        params.set_logprob_thold(options.logprob_thold);
+        if let Some(audio_ctx) = options.audio_ctx {
+            params.set_audio_ctx(audio_ctx);
+        }
 
-        #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
-        let threads = threads.map_or_else(|| num_cpus::get() as i32, i32::from);
-
+        let threads = thread_count(options.threads)?;
         trace!("Using {} threads", threads);
+        params.set_n_threads(i32::try_from(threads).unwrap_or(i32::MAX));
 
-        params.set_n_threads(threads);
+        Ok(params)
+    }
 
-        let st = Instant::now();
-        let mut state = self.context.create_state().expect("failed to create state");
+    /// Runs whisper over the audio with the given parameters, after trimming and
+    /// padding it as `options` say, with a state from `states` if given, and returns
+    /// the state holding the results, along with the stretches of speech found while
+    /// trimming.
+    fn infer<'p, 'a>(
+        &'a self,
+        mut params: FullParams,
+        audio: &[f32],
+        options: &TranscribeOptions,
+        states: Option<&'p StatePool<'a>>,
+    ) -> Result<(State<'p, 'a>, Option<SpeechRanges>), ModelError> {
+        if states.is_some() {
+            // A pooled state still holds the text of its last transcription, which
+            // whisper.cpp would carry over as context. A new state holds none, so this
+            // changes nothing else.
+            params.set_no_context(true);
+        }
+        let (audio, speech_ranges) = match &options.trim_trailing_silence {
+            Some(vad) => {
+                let (audio, speech_ranges) = vad.trim_and_find_speech(audio);
+                (audio, Some(speech_ranges))
+            }
+            None => (audio, None),
+        };
+        // whisper.cpp silently skips anything shorter than a second.
+        let min_length = options.pad_to.map_or(Duration::from_secs(1), |pad_to| {
+            pad_to.max(Duration::from_secs(1))
+        });
+        let audio = transcode::pad_to(audio, min_length);
+        let mut state = self.state(states)?;
         trace!("Transcribing audio with WhisperState");
-        state.full(params, audio).expect("failed to transcribe");
+        state
+            .full(params, &audio)
+            .map_err(ModelError::WhisperError)?;
+        Ok((state, speech_ranges))
+    }
+
+    /// Runs whisper over the audio with the given parameters, and collects the
+    /// resulting segments (and words, if requested) into a [Transcript].
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(samples = audio.len()))
+    )]
+    fn run_full<'a>(
+        &'a self,
+        mut params: FullParams,
+        audio: &[f32],
+        options: &TranscribeOptions,
+        states: Option<&StatePool<'a>>,
+    ) -> Result<Transcript, ModelError> {
+        let word_timestamps = options.word_timestamps;
+        let audio_duration = transcode::samples_to_duration(audio.len());
+        // whisper.cpp reads the grammar during `full`, so it must outlive inference.
+        let grammar = options.grammar.as_ref().map(Grammar::elements);
+        if let (Some(elements), Some(grammar)) = (&grammar, &options.grammar) {
+            params.set_grammar(Some(elements));
+            params.set_start_rule(grammar.root());
+            params.set_grammar_penalty(options.grammar_penalty);
+        }
+        let st = Instant::now();
+        let (state, speech_ranges) = self.infer(params, audio, options, states)?;
+        let inference_time = st.elapsed();
+        let extraction_start = Instant::now();
 
-        let num_segments = state.full_n_segments().expect("failed to get segments");
+        let num_segments = state.full_n_segments().map_err(ModelError::WhisperError)?;
         trace!("Number of segments: {}", num_segments);
 
         let mut words = Vec::new();
@@ -241,43 +1012,89 @@ impl Model {
                 .full_get_segment_t1(segment_idx)
                 .map_err(ModelError::WhisperError)?;
 
-            utterances.push(Utterance { start, stop, text });
-
-            if !word_timestamps {
-                trace!("Skipping word timestamps");
-                continue;
-            }
-
-            trace!("Getting word timestamps for segment {}", segment_idx);
+            trace!("Getting tokens for segment {}", segment_idx);
 
             let num_tokens = state
                 .full_n_tokens(segment_idx)
                 .map_err(ModelError::WhisperError)?;
 
+            let mut sum_logprob = 0.0;
+            let mut text_tokens = 0u16;
+            let mut assembler = TokenAssembler::default();
             for t in 0..num_tokens {
-                let text = state
-                    .full_get_token_text(segment_idx, t)
-                    .map_err(ModelError::WhisperError)?;
                 let token_data = state
                     .full_get_token_data(segment_idx, t)
                     .map_err(ModelError::WhisperError)?;
+                // Tokens can end halfway through a character, so their text is only
+                // decoded once the character is complete, from the raw bytes of the
+                // token in the vocabulary.
+                let token_bytes = self
+                    .context
+                    .token_to_cstr(token_data.id)
+                    .map_err(ModelError::WhisperError)?
+                    .to_bytes();
 
-                if text.starts_with("[_") {
+                let special = token_bytes.starts_with(b"[_");
+                if !special {
+                    sum_logprob += token_data.plog;
+                    text_tokens = text_tokens.saturating_add(1);
+                }
+                if !word_timestamps || (special && !options.include_special_tokens) {
                     continue;
                 }
 
-                words.push(Utterance {
-                    text,
-                    start: token_data.t0,
-                    stop: token_data.t1,
-                });
+                words.extend(assembler.push(
+                    token_bytes,
+                    token_data.t0,
+                    token_data.t1,
+                    token_data.p,
+                ));
+            }
+            words.extend(assembler.finish());
+
+            let avg_logprob = (text_tokens > 0).then(|| sum_logprob / f32::from(text_tokens));
+            if let Some(avg_logprob) = avg_logprob.filter(|&avg| avg < options.logprob_thold) {
+                // With fallback enabled, whisper.cpp decodes such segments again at
+                // higher temperatures, unless it judged the window to have no speech,
+                // so this one may have gone through every temperature.
+                if options.temperature_inc > 0.0 {
+                    debug!(
+                        "Segment {segment_idx} ({start}..{stop}) is below logprob_thold, \
+                         possibly after temperature fallback: avg_logprob {avg_logprob}"
+                    );
+                } else {
+                    debug!(
+                        "Segment {segment_idx} ({start}..{stop}) is below logprob_thold: \
+                         avg_logprob {avg_logprob}"
+                    );
+                }
             }
+            utterances.push(Utterance {
+                start,
+                stop,
+                compression_ratio: transcript::compression_ratio(&text),
+                avg_logprob,
+                text,
+                probability: None,
+                language: None,
+                seek: Some(transcript::window_start(start)),
+                speaker: None,
+            });
         }
 
         Ok(Transcript {
             utterances,
             processing_time: Instant::now().duration_since(st),
             word_utterances: if word_timestamps { Some(words) } else { None },
+            timings: Timings {
+                decode: Duration::ZERO,
+                inference: inference_time,
+                extraction: extraction_start.elapsed(),
+            },
+            audio_duration: Some(audio_duration),
+            clipping_ratio: None,
+            source_format: None,
+            speech_ranges,
         })
     }
 }
@@ -291,11 +1108,72 @@ pub enum ModelError {
     DownloadError(Box<ureq::Error>),
     /// [`std::io::Error`]. Error reading model.
     IoError(std::io::Error),
-    /// [`AudioDecodeError`]. Error decoding audio.
-    AudioDecodeError,
+    /// [`serde_json::Error`]. A transcript, or options, couldn't be serialized to JSON.
+    SerializationError(serde_json::Error),
+    /// The audio isn't in a format that can be decoded, or, for interleaved samples,
+    /// has zero channels or a sample rate of zero. Only MP3 files, decoded by
+    /// Symphonia, are told apart from [`ModelError::CorruptData`]; damaged WAV, FLAC
+    /// and OGG Vorbis files are reported as unsupported too.
+    UnsupportedFormat,
+    /// The audio is in a supported format, but is damaged or truncated and couldn't be
+    /// decoded. Contains the decoder's description of the problem.
+    CorruptData(String),
+    /// The audio could be decoded, but contains no audio track or no samples.
+    EmptyStream,
+    /// The requested time range is empty or lies outside of the audio.
+    InvalidRange,
+    /// The language code isn't one supported by whisper. See [`Model::languages`].
+    InvalidLanguage(String),
+    /// `threads` was `Some(0)`. whisper needs at least one thread; use `None` for one
+    /// thread per logical CPU.
+    InvalidThreadCount,
+    /// `translate` was set, but the model is English-only and can't translate. See
+    /// [`Model::supports_translation`].
+    TranslationUnsupported,
+    /// Word timestamps were needed, but `word_timestamps` wasn't `true` when
+    /// transcribing.
+    WordTimestampsUnavailable,
+    /// Subtitles couldn't be parsed. Contains the 1-based number of the first line
+    /// that couldn't be understood.
+    SubtitleParseError(usize),
+    /// A GBNF grammar couldn't be parsed. Contains a description of the problem.
+    /// See [`Grammar::parse`].
+    InvalidGrammar(String),
+    /// The temperatures given to
+    /// [`TranscribeOptions::with_temperatures`] can't be expressed as a starting
+    /// temperature and an increment, which is all whisper.cpp takes.
+    InvalidTemperatures,
+    /// The operation was cancelled, or its deadline was reached, before it finished.
+    /// See [`Model::download_cancellable`].
+    Cancelled,
 }
 
-#[derive(Debug, EnumIter)]
+/// Number of threads to run whisper with: `threads`, or the number of logical CPUs if
+/// `None`.
+///
+/// # Errors
+/// - [`ModelError::InvalidThreadCount`], if `threads` is zero, which whisper.cpp
+///   doesn't handle and may hang on.
+pub(crate) fn thread_count(threads: Option<u16>) -> Result<usize, ModelError> {
+    match threads {
+        Some(0) => Err(ModelError::InvalidThreadCount),
+        Some(threads) => Ok(usize::from(threads)),
+        None => Ok(num_cpus::get()),
+    }
+}
+
+/// Most bytes of a model read at once while downloading it. Cancellation is checked
+/// between blocks.
+const DOWNLOAD_BLOCK_LEN: usize = 64 * 1024;
+
+/// Length of the magic number and hyperparameters at the start of a whisper GGML file.
+const GGML_HEADER_LEN: usize = 48;
+
+/// Magic number at the start of every GGML file, `"ggml"` read as a little-endian
+/// integer.
+const GGML_MAGIC: u32 = 0x6767_6d6c;
+
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter)]
 pub enum ModelType {
     /// Tiny Whisper model - finetuned for English.
     /// Size: 75 MB.
@@ -341,24 +1219,184 @@ pub enum ModelType {
     LargeV3,
 }
 
+/// What to prioritize when picking a model with [`ModelType::recommend`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Fastest transcription, at the cost of accuracy.
+    Speed,
+    /// A reasonable middle ground, suitable for most uses.
+    Balanced,
+    /// Most accurate transcription, at the cost of speed and memory.
+    Accuracy,
+}
+
+impl ModelType {
+    /// Every model type, smallest first, in declaration order, e.g. to list them in a
+    /// UI without importing [`strum::IntoEnumIterator`] just to call `ModelType::iter`.
+    #[must_use]
+    pub const fn all() -> &'static [Self] {
+        &[
+            Self::TinyEn,
+            Self::Tiny,
+            Self::BaseEn,
+            Self::Base,
+            Self::SmallEn,
+            Self::Small,
+            Self::MediumEn,
+            Self::Medium,
+            Self::LargeV1,
+            Self::LargeV2,
+            Self::LargeV3,
+        ]
+    }
+
+    /// Name of the model's file, such as `ggml-base.en.bin`, as published by
+    /// whisper.cpp and cached by [`Model::load_or_download`].
+    #[must_use]
+    pub const fn file_name(&self) -> &'static str {
+        match self {
+            Self::TinyEn => "ggml-tiny.en.bin",
+            Self::Tiny => "ggml-tiny.bin",
+            Self::BaseEn => "ggml-base.en.bin",
+            Self::Base => "ggml-base.bin",
+            Self::SmallEn => "ggml-small.en.bin",
+            Self::Small => "ggml-small.bin",
+            Self::MediumEn => "ggml-medium.en.bin",
+            Self::Medium => "ggml-medium.bin",
+            Self::LargeV1 => "ggml-large-v1.bin",
+            Self::LargeV2 => "ggml-large-v2.bin",
+            Self::LargeV3 => "ggml-large-v3.bin",
+        }
+    }
+
+    /// Number of mel bands the model expects: 128 for Large V3, 80 for every other
+    /// model. See [`Model::n_mels`].
+    #[must_use]
+    pub const fn n_mels(&self) -> usize {
+        match self {
+            Self::LargeV3 => 128,
+            _ => 80,
+        }
+    }
+
+    /// Identifies a model file from its GGML header rather than its name, which users
+    /// may have changed. Reads the model's hyperparameters: the number of layers gives
+    /// its size, the vocabulary whether it's English-only and the number of mel bands
+    /// whether it's Large V3.
+    ///
+    /// Large V1 and V2 have identical hyperparameters, so both are reported as
+    /// [`ModelType::LargeV2`].
+    ///
+    /// # Returns
+    /// `None` if the file can't be read, isn't a whisper GGML model, or is a model
+    /// that isn't one of these types, such as a distilled or turbo model.
+    #[must_use]
+    pub fn from_file(path: impl AsRef<Path>) -> Option<Self> {
+        let mut header = [0; GGML_HEADER_LEN];
+        File::open(path).ok()?.read_exact(&mut header).ok()?;
+        Self::from_header(&header)
+    }
+
+    /// Identifies a model from the first [`GGML_HEADER_LEN`] bytes of its file. See
+    /// [`ModelType::from_file`].
+    fn from_header(header: &[u8; GGML_HEADER_LEN]) -> Option<Self> {
+        // The magic number and hyperparameters are little-endian 32-bit integers.
+        let fields = header
+            .chunks_exact(4)
+            .map(|field| u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+            .collect::<Vec<_>>();
+        let [magic, n_vocab, _n_audio_ctx, _n_audio_state, _n_audio_head, n_audio_layer, _n_text_ctx, _n_text_state, _n_text_head, n_text_layer, n_mels, _ftype] =
+            fields[..]
+        else {
+            return None;
+        };
+        if magic != GGML_MAGIC || n_audio_layer != n_text_layer {
+            return None;
+        }
+        // English-only models have one token fewer, having no translation token.
+        let english = n_vocab < 51865;
+        Some(match (n_audio_layer, english, n_mels) {
+            (4, true, 80) => Self::TinyEn,
+            (4, false, 80) => Self::Tiny,
+            (6, true, 80) => Self::BaseEn,
+            (6, false, 80) => Self::Base,
+            (12, true, 80) => Self::SmallEn,
+            (12, false, 80) => Self::Small,
+            (24, true, 80) => Self::MediumEn,
+            (24, false, 80) => Self::Medium,
+            (32, false, 80) => Self::LargeV2,
+            (32, false, 128) => Self::LargeV3,
+            _ => return None,
+        })
+    }
+
+    /// Approximate memory needed to run the model with whisper.cpp, in megabytes.
+    #[must_use]
+    pub const fn required_memory_mb(&self) -> u64 {
+        match self {
+            Self::TinyEn | Self::Tiny => 273,
+            Self::BaseEn | Self::Base => 388,
+            Self::SmallEn | Self::Small => 852,
+            Self::MediumEn | Self::Medium => 2100,
+            Self::LargeV1 | Self::LargeV2 | Self::LargeV3 => 3900,
+        }
+    }
+
+    /// Rough real-time factor of transcribing on a recent desktop CPU, for
+    /// [`Model::estimate_duration`].
+    const fn typical_real_time_factor(&self) -> f32 {
+        match self {
+            Self::TinyEn | Self::Tiny => 0.05,
+            Self::BaseEn | Self::Base => 0.1,
+            Self::SmallEn | Self::Small => 0.3,
+            Self::MediumEn | Self::Medium => 0.9,
+            Self::LargeV1 | Self::LargeV2 | Self::LargeV3 => 1.8,
+        }
+    }
+
+    /// Recommends a model for the given constraints.
+    ///
+    /// # Arguments
+    /// - `english_only`: Whether the audio is only ever in English. English-only
+    ///   models are more accurate than multilingual models of the same size, but can't
+    ///   transcribe other languages or translate.
+    /// - `priority`: [`Priority`] of speed versus accuracy.
+    /// - `available_memory_mb`: Memory available to the model, in megabytes. If the
+    ///   recommended model doesn't fit, the next smaller one is picked instead.
+    /// # Returns
+    /// [`ModelType`]. If no model fits in the available memory, the tiny model.
+    #[must_use]
+    pub fn recommend(
+        english_only: bool,
+        priority: Priority,
+        available_memory_mb: Option<u64>,
+    ) -> Self {
+        let candidates: &[Self] = match (english_only, priority) {
+            (true, Priority::Accuracy) => &[Self::MediumEn, Self::SmallEn, Self::BaseEn],
+            (true, Priority::Balanced) => &[Self::SmallEn, Self::BaseEn],
+            (true, Priority::Speed) => &[Self::BaseEn],
+            (false, Priority::Accuracy) => &[Self::LargeV3, Self::Medium, Self::Small, Self::Base],
+            (false, Priority::Balanced) => &[Self::Small, Self::Base],
+            (false, Priority::Speed) => &[Self::Base],
+        };
+        let fits = |model: &&Self| {
+            available_memory_mb.is_none_or(|memory| model.required_memory_mb() <= memory)
+        };
+        let smallest = if english_only {
+            Self::TinyEn
+        } else {
+            Self::Tiny
+        };
+        candidates.iter().find(fits).cloned().unwrap_or(smallest)
+    }
+}
+
 impl Display for ModelType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-"
-        )?;
-        match self {
-            Self::TinyEn => write!(f, "tiny.en.bin"),
-            Self::Tiny => write!(f, "tiny.bin"),
-            Self::BaseEn => write!(f, "base.en.bin"),
-            Self::Base => write!(f, "base.bin"),
-            Self::SmallEn => write!(f, "small.en.bin"),
-            Self::Small => write!(f, "small.bin"),
-            Self::MediumEn => write!(f, "medium.en.bin"),
-            Self::Medium => write!(f, "medium.bin"),
-            Self::LargeV1 => write!(f, "large-v1.bin"),
-            Self::LargeV2 => write!(f, "large-v2.bin"),
-            Self::LargeV3 => write!(f, "large-v3.bin"),
-        }
+            "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/{}",
+            self.file_name()
+        )
     }
 }
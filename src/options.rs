@@ -0,0 +1,462 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use whisper_rs::SamplingStrategy;
+
+use crate::{grammar::Grammar, transcode, vad::EnergyVad, ModelError};
+
+/// Decoding strategy used by whisper.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Sampling {
+    /// Greedy decoding. Picks the most likely token at every step, which is fast
+    /// but less robust on difficult audio.
+    ///
+    /// At a non-zero temperature, whether [`TranscribeOptions::temperature`] or a
+    /// temperature fallback, `best_of` candidates are sampled and the most likely is
+    /// kept. `best_of` above 1 is a cheaper way to make greedy decoding more robust
+    /// than switching to beam search.
+    Greedy {
+        /// Number of candidates to sample when decoding at a non-zero temperature.
+        /// whisper.cpp's default is 5. Has no effect at temperature 0.
+        best_of: i32,
+    },
+    /// Beam search decoding. Slower, but generally more accurate.
+    BeamSearch {
+        /// Number of beams to keep.
+        beam_size: i32,
+        /// Beam search patience factor.
+        patience: f32,
+    },
+}
+
+impl Sampling {
+    pub(crate) const fn to_strategy(self) -> SamplingStrategy {
+        match self {
+            Self::Greedy { best_of } => SamplingStrategy::Greedy { best_of },
+            Self::BeamSearch {
+                beam_size,
+                patience,
+            } => SamplingStrategy::BeamSearch {
+                beam_size,
+                patience,
+            },
+        }
+    }
+}
+
+/// Options for a transcription. Pass to [`Model::transcribe`](crate::Model::transcribe).
+/// They only apply to that call: one model can transcribe with different options,
+/// even concurrently, without being loaded again.
+///
+/// Start from [`TranscribeOptions::default`] or one of the presets and adjust the
+/// fields you care about:
+/// - [`TranscribeOptions::dictation`]: low-latency, single-speaker clean audio.
+/// - [`TranscribeOptions::subtitles`]: films, podcasts and other media.
+/// - [`TranscribeOptions::deterministic`]: reproducible output for tests.
+/// - [`TranscribeOptions::openai_default`]: the defaults of the Python implementation.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscribeOptions {
+    /// Whether to translate the text to English. Needs a multilingual model, see
+    /// [`Model::supports_translation`](crate::Model::supports_translation).
+    pub translate: bool,
+    /// Whether to output word timestamps.
+    pub word_timestamps: bool,
+    /// Optional initial prompt to whisper model.
+    pub initial_prompt: Option<String>,
+    /// Optional language setting for whisper model. `None` or `"auto"` auto-detects
+    /// the language. Must be one of [`Model::languages`](crate::Model::languages).
+    pub language: Option<String>,
+    /// Number of threads to use. `None` will use the number of cores from
+    /// the `num_cpus` crate. `Some(0)` is rejected with
+    /// [`ModelError::InvalidThreadCount`](crate::ModelError::InvalidThreadCount).
+    pub threads: Option<u16>,
+    /// Decoding strategy.
+    pub sampling: Sampling,
+    /// Force whisper to output a single segment. Only useful for short clips.
+    pub single_segment: bool,
+    /// Don't feed previously decoded text back to whisper as context.
+    pub no_context: bool,
+    /// Suppress blank outputs at the beginning of sampling.
+    pub suppress_blank: bool,
+    /// Suppress non-speech tokens, such as `[MUSIC]` or `(laughs)`.
+    pub suppress_non_speech_tokens: bool,
+    /// Initial sampling temperature. 0 always picks the most likely token.
+    #[serde(default)]
+    pub temperature: f32,
+    /// How much to raise the temperature by each time a segment fails the
+    /// `entropy_thold` or `logprob_thold` checks and is decoded again. 0 disables this
+    /// fallback.
+    #[serde(default = "default_temperature_inc")]
+    pub temperature_inc: f32,
+    /// Segments whose token entropy is below this threshold are considered repetitive
+    /// and decoded again at a higher temperature. This is whisper.cpp's equivalent of
+    /// the Python implementation's compression ratio threshold. Raise it to catch more repetition.
+    pub entropy_thold: f32,
+    /// Segments whose average log probability is below this threshold are decoded
+    /// again at a higher temperature.
+    pub logprob_thold: f32,
+    /// Size of the audio context for the encoder. `None` uses the model's full context
+    /// of 1500, which covers 30 seconds (50 per second); whisper pads all audio to this.
+    /// For clips much shorter than 30 seconds, a smaller context such as
+    /// `seconds * 50 + 64` is considerably faster, at a small cost in accuracy. Must be
+    /// at most [`Model::max_audio_ctx`](crate::Model::max_audio_ctx).
+    pub audio_ctx: Option<i32>,
+    /// Reset whisper's context about this often, to stop long recordings drifting off
+    /// topic or falling into repetition loops. The audio is transcribed in pieces of
+    /// at most this length, each cut at the quietest moment near the limit, and no
+    /// text is carried over from one piece to the next. `None` never resets the
+    /// context. Only matters if `no_context` is `false`.
+    #[serde(default)]
+    pub reset_context_every: Option<Duration>,
+    /// Audio longer than this is transcribed in pieces of at most this length, each
+    /// cut at the quietest moment near the limit, and the transcripts are joined.
+    /// Unless `no_context` is `true`, the end of each piece's text is carried over as
+    /// the prompt for the next, so the pieces read as one transcript. `None`
+    /// transcribes any length in one go. Defaults to 30 minutes.
+    #[serde(default = "default_chunk_longer_than")]
+    pub chunk_longer_than: Option<Duration>,
+    /// Constrains the output to text accepted by this [`Grammar`], e.g. a fixed set of
+    /// voice commands. `None` doesn't constrain the output.
+    #[serde(default)]
+    pub grammar: Option<Grammar>,
+    /// How strongly tokens that don't match `grammar` are penalised. whisper.cpp's
+    /// default of 100 all but rules them out.
+    #[serde(default = "default_grammar_penalty")]
+    pub grammar_penalty: f32,
+    /// Keep special tokens, such as `[_BEG_]` and `[_TT_150]`, in
+    /// [`Transcript::word_utterances`](crate::transcript::Transcript::word_utterances).
+    /// They are dropped by default, but can help when debugging timing alignment.
+    #[serde(default)]
+    pub include_special_tokens: bool,
+    /// Pad audio shorter than this with trailing silence before handing it to whisper.
+    /// whisper.cpp pads everything to 30 seconds internally, which on very short clips
+    /// can end in a hallucinated sentence; padding a clip with real silence, e.g. to
+    /// a few seconds, often stops that. Audio is always padded to at least one second,
+    /// as whisper.cpp skips anything shorter. Timestamps are unaffected.
+    #[serde(default)]
+    pub pad_to: Option<Duration>,
+    /// Cut off the silence at the end of the audio, as detected by this
+    /// [`EnergyVad`], before handing it to whisper, so it can't hallucinate text in
+    /// it. Applied before `pad_to`. The stretches of speech it finds along the way are
+    /// in [`Transcript::speech_ranges`]. `None` keeps the audio as is.
+    ///
+    /// [`Transcript::speech_ranges`]: crate::transcript::Transcript::speech_ranges
+    #[serde(default)]
+    pub trim_trailing_silence: Option<EnergyVad>,
+    /// Only transcribe the first this much of the audio, e.g. for a quick preview of a
+    /// long file; combine with a small `audio_ctx` for an even faster one. Files are
+    /// only decoded up to this point, so their clipping ratio is that of the part
+    /// transcribed. [`Transcript::audio_duration`] is still that of the whole file if
+    /// its container records it, as most do, and otherwise that of the part decoded.
+    /// `None` transcribes everything.
+    ///
+    /// [`Transcript::audio_duration`]: crate::transcript::Transcript::audio_duration
+    #[serde(default)]
+    pub max_transcribe_duration: Option<Duration>,
+}
+
+/// The same options [`Model::transcribe_audio`](crate::Model::transcribe_audio) uses
+/// when given `false`, `false` and `None` for everything else: beam search with 5
+/// beams and a patience of 1, no translation, no initial prompt, auto-detected
+/// language and one thread per logical CPU.
+impl Default for TranscribeOptions {
+    fn default() -> Self {
+        Self {
+            translate: false,
+            word_timestamps: false,
+            initial_prompt: None,
+            language: None,
+            threads: None,
+            sampling: Sampling::BeamSearch {
+                beam_size: 5,
+                patience: 1.0,
+            },
+            single_segment: false,
+            no_context: true,
+            suppress_blank: true,
+            suppress_non_speech_tokens: false,
+            temperature: 0.0,
+            temperature_inc: default_temperature_inc(),
+            entropy_thold: 2.4,
+            logprob_thold: -1.0,
+            audio_ctx: None,
+            reset_context_every: None,
+            chunk_longer_than: default_chunk_longer_than(),
+            grammar: None,
+            grammar_penalty: default_grammar_penalty(),
+            include_special_tokens: false,
+            pad_to: None,
+            trim_trailing_silence: None,
+            max_transcribe_duration: None,
+        }
+    }
+}
+
+/// Tolerance when comparing temperatures, as whisper.cpp does when deciding whether to
+/// fall back to another temperature.
+const TEMPERATURE_EPSILON: f32 = 1e-6;
+
+/// whisper.cpp's default temperature increment.
+const fn default_temperature_inc() -> f32 {
+    0.2
+}
+
+/// whisper.cpp's default grammar penalty.
+const fn default_grammar_penalty() -> f32 {
+    100.0
+}
+
+/// Audio longer than half an hour is transcribed in pieces by default.
+#[allow(clippy::unnecessary_wraps)]
+const fn default_chunk_longer_than() -> Option<Duration> {
+    Some(Duration::from_secs(30 * 60))
+}
+
+impl TranscribeOptions {
+    /// The part of `audio` to transcribe, cut off at `max_transcribe_duration`.
+    pub(crate) fn limit<'a>(&self, audio: &'a [f32]) -> &'a [f32] {
+        match self.max_transcribe_duration {
+            Some(max) => &audio[..transcode::duration_to_samples(max).min(audio.len())],
+            None => audio,
+        }
+    }
+
+    /// Preset for dictation: a single speaker talking into a decent microphone, in
+    /// short bursts. Uses greedy decoding for latency, treats every clip as one
+    /// segment, ignores previous context and aggressively suppresses blanks and
+    /// non-speech tokens.
+    #[must_use]
+    pub fn dictation() -> Self {
+        Self {
+            sampling: Sampling::Greedy { best_of: 1 },
+            single_segment: true,
+            no_context: true,
+            suppress_blank: true,
+            suppress_non_speech_tokens: true,
+            ..Self::default()
+        }
+    }
+
+    /// Preset for reproducible output, e.g. for snapshot tests. Uses greedy decoding
+    /// at temperature 0 and disables temperature fallback, so nothing is sampled at
+    /// random.
+    ///
+    /// The same audio then gives the same transcript with the same model, the same
+    /// number of `threads`, the same build of whisper.cpp and the same hardware.
+    /// Changing any of these can still change the output slightly, as floating point
+    /// results depend on them.
+    #[must_use]
+    pub fn deterministic() -> Self {
+        Self {
+            sampling: Sampling::Greedy { best_of: 1 },
+            temperature: 0.0,
+            temperature_inc: 0.0,
+            ..Self::default()
+        }
+    }
+
+    /// Preset mirroring the defaults of `transcribe()` in the Python implementation,
+    /// the `openai-whisper` package, for users migrating from it: greedy decoding,
+    /// falling back to temperatures of 0.2, 0.4, 0.6, 0.8 and 1.0 when a segment fails
+    /// the thresholds, a compression ratio threshold of 2.4 (`entropy_thold` in
+    /// whisper.cpp), a log probability threshold of -1, previous text as context, and
+    /// suppressed blanks and non-speech tokens.
+    ///
+    /// The output still won't match exactly: whisper.cpp measures repetition by
+    /// entropy rather than compression ratio, ignores the no-speech threshold and uses
+    /// its own timestamp heuristics. The package's `whisper` command line tool uses
+    /// beam search with 5 beams at temperature 0 instead; set `sampling` to
+    /// [`Sampling::BeamSearch`] with a `beam_size` of 5 and a `patience` of 1 to get
+    /// closer to it.
+    #[must_use]
+    pub fn openai_default() -> Self {
+        Self {
+            sampling: Sampling::Greedy { best_of: 1 },
+            temperature: 0.0,
+            temperature_inc: 0.2,
+            entropy_thold: 2.4,
+            logprob_thold: -1.0,
+            no_context: false,
+            suppress_blank: true,
+            suppress_non_speech_tokens: true,
+            ..Self::default()
+        }
+    }
+
+    /// Preset for subtitling media: long recordings, music and several speakers.
+    /// Uses beam search and carries context between segments so names and terms
+    /// stay consistent across the whole file.
+    #[must_use]
+    pub fn subtitles() -> Self {
+        Self {
+            sampling: Sampling::BeamSearch {
+                beam_size: 5,
+                patience: 1.0,
+            },
+            single_segment: false,
+            no_context: false,
+            suppress_blank: true,
+            suppress_non_speech_tokens: false,
+            ..Self::default()
+        }
+    }
+
+    /// Sets whether to translate the text to English.
+    #[must_use]
+    pub const fn with_translate(mut self, translate: bool) -> Self {
+        self.translate = translate;
+        self
+    }
+
+    /// Sets whether to output word timestamps.
+    #[must_use]
+    pub const fn with_word_timestamps(mut self, word_timestamps: bool) -> Self {
+        self.word_timestamps = word_timestamps;
+        self
+    }
+
+    /// Sets the initial prompt to whisper model.
+    #[must_use]
+    pub fn with_initial_prompt(mut self, initial_prompt: impl Into<String>) -> Self {
+        self.initial_prompt = Some(initial_prompt.into());
+        self
+    }
+
+    /// Sets the language, such as `"en"`. See [`TranscribeOptions::language`].
+    #[must_use]
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    /// Sets the number of threads to use.
+    #[must_use]
+    pub const fn with_threads(mut self, threads: u16) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Temperatures whisper decodes a segment at, in order, until it passes the
+    /// `entropy_thold` and `logprob_thold` checks: `temperature`, then up by
+    /// `temperature_inc` at a time as long as it's at most 1, like whisper.cpp.
+    #[must_use]
+    pub fn temperatures(&self) -> Vec<f32> {
+        if self.temperature_inc <= 0.0 {
+            return vec![self.temperature];
+        }
+        std::iter::successors(Some(self.temperature), |temperature| {
+            Some(temperature + self.temperature_inc)
+        })
+        .take_while(|&temperature| temperature < 1.0 + TEMPERATURE_EPSILON)
+        .collect()
+    }
+
+    /// Sets the temperatures to decode a segment at, in order, until it passes the
+    /// `entropy_thold` and `logprob_thold` checks, such as `[0.0, 0.4, 0.8]` for a
+    /// more aggressive fallback than the default `[0.0, 0.2, 0.4, 0.6, 0.8, 1.0]`.
+    ///
+    /// whisper.cpp only takes a starting temperature and an increment, and always
+    /// keeps going up to 1, so the temperatures must be evenly spaced and continue
+    /// until the next step would be above 1. A single temperature disables the
+    /// fallback. See [`TranscribeOptions::temperatures`].
+    /// # Errors
+    /// - [`ModelError::InvalidTemperatures`], if the temperatures are empty, outside
+    ///   of 0 to 1, not evenly spaced or stop before 1.
+    pub fn with_temperatures(mut self, temperatures: &[f32]) -> Result<Self, ModelError> {
+        let (&first, rest) = temperatures
+            .split_first()
+            .ok_or(ModelError::InvalidTemperatures)?;
+        let increment = rest.first().map_or(0.0, |&second| second - first);
+        self.temperature = first;
+        self.temperature_inc = increment;
+        let expected = self.temperatures();
+        let matches = expected.len() == temperatures.len()
+            && expected
+                .iter()
+                .zip(temperatures)
+                .all(|(expected, temperature)| {
+                    (expected - temperature).abs() < TEMPERATURE_EPSILON
+                });
+        // A decreasing or repeated temperature gives fewer temperatures than listed.
+        if !(0.0..=1.0).contains(&first) || !matches {
+            return Err(ModelError::InvalidTemperatures);
+        }
+        Ok(self)
+    }
+
+    /// Sets the decoding strategy.
+    #[must_use]
+    pub const fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+}
+
+/// Noise reduction function. Takes 16 kHz single-channel samples and returns the
+/// denoised samples.
+pub type Denoiser = fn(&[f32]) -> Vec<f32>;
+
+/// Preprocessing function for audio that still has all of its channels. Takes
+/// interleaved 16 kHz samples and their number of channels, and returns processed
+/// samples with the same number of channels.
+pub type Preprocessor = fn(&[f32], u16) -> Vec<f32>;
+
+/// Options for decoding audio before it is transcribed. Pass to
+/// [`Model::transcribe_with_decode_options`](crate::Model::transcribe_with_decode_options).
+#[derive(Debug, Clone, Default)]
+pub struct DecodeOptions {
+    /// Preprocessing pass, run on the decoded audio after it is resampled to 16 kHz
+    /// but before it is downmixed to a single channel, e.g. for stereo-aware noise
+    /// reduction. Runs before [`DecodeOptions::denoise`].
+    pub preprocess: Option<Preprocessor>,
+    /// Noise reduction pass, run on the 16 kHz single-channel samples after decoding
+    /// and before transcription. With the `nnnoiseless` feature,
+    /// [`DecodeOptions::nnnoiseless`] sets this to an RNNoise-based denoiser.
+    pub denoise: Option<Denoiser>,
+}
+
+impl DecodeOptions {
+    /// Denoise audio with [nnnoiseless](https://docs.rs/nnnoiseless), a port of
+    /// `RNNoise`. Works best on speech recorded with steady background noise, such as
+    /// wind, traffic or fans.
+    #[cfg(feature = "nnnoiseless")]
+    #[must_use]
+    pub fn nnnoiseless() -> Self {
+        Self {
+            denoise: Some(crate::transcode::denoise_nnnoiseless),
+            ..Self::default()
+        }
+    }
+}
+
+#[test]
+fn test_temperatures() {
+    let options = TranscribeOptions::default();
+    assert_eq!(options.temperatures().len(), 6);
+    assert_eq!(TranscribeOptions::deterministic().temperatures(), [0.0]);
+
+    let options = TranscribeOptions::default()
+        .with_temperatures(&[0.0, 0.4, 0.8])
+        .unwrap();
+    assert_eq!(options.temperatures(), [0.0, 0.4, 0.8]);
+    assert!((options.temperature_inc - 0.4).abs() < 1e-6);
+    let options = TranscribeOptions::default()
+        .with_temperatures(&[0.5])
+        .unwrap();
+    assert_eq!(options.temperatures(), [0.5]);
+
+    for invalid in [
+        &[][..],
+        &[0.0, 0.2, 0.4],
+        &[0.0, 0.3, 0.5, 0.7, 0.9],
+        &[0.0, 0.0],
+        &[0.8, 0.6],
+        &[1.5],
+    ] {
+        assert!(matches!(
+            TranscribeOptions::default().with_temperatures(invalid),
+            Err(ModelError::InvalidTemperatures)
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    num::NonZeroUsize,
+    ops::{Deref, DerefMut},
+    sync::{Condvar, Mutex, PoisonError},
+};
+
+use log::trace;
+use whisper_rs::WhisperState;
+
+use crate::{transcript::Transcript, Model, ModelError, TranscribeOptions};
+
+/// A fixed number of whisper states for a model, which transcriptions take turns
+/// using, for servers handling many requests at once. Created by [`ModelPool::new`].
+///
+/// A plain [`Model`] allocates a fresh state, tens to hundreds of megabytes, for every
+/// transcription and frees it afterwards, and nothing stops a burst of requests from
+/// allocating more states than fit in memory. A pool allocates its states once, up
+/// front, and hands one to each transcription, so at most `size` transcriptions run at
+/// once; the rest wait for a state to be returned. The states borrow the model, so the
+/// pool does too: keep the model in an [`Arc`](std::sync::Arc) or a `static`, and share
+/// the pool between threads like the model.
+pub struct ModelPool<'a> {
+    model: &'a Model,
+    states: StatePool<'a>,
+}
+
+impl<'a> ModelPool<'a> {
+    /// Creates a pool of `size` whisper states for `model`. All states are allocated
+    /// right away, so running out of memory, or GPU memory, shows up here rather than
+    /// under load.
+    ///
+    /// # Arguments
+    /// - `model`: [`Model`] to transcribe with.
+    /// - `size`: Number of states, and so the most transcriptions that run at once.
+    /// # Errors
+    /// - [`ModelError::WhisperError`], if a state can't be allocated.
+    pub fn new(model: &'a Model, size: NonZeroUsize) -> Result<Self, ModelError> {
+        let states = (0..size.get())
+            .map(|_| {
+                model
+                    .context
+                    .create_state()
+                    .map_err(ModelError::WhisperError)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        trace!("Created a pool of {size} whisper states");
+        Ok(Self {
+            model,
+            states: StatePool {
+                states: Mutex::new(states),
+                returned: Condvar::new(),
+            },
+        })
+    }
+
+    /// Transcribes audio like [`Model::transcribe`], with a state from the pool,
+    /// waiting for one to be returned first if they are all in use.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe(
+        &self,
+        audio: impl AsRef<[u8]>,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        self.model
+            .transcribe_with_samples_using(audio, options, Some(&self.states))
+            .map(|(transcript, _)| transcript)
+    }
+
+    /// Transcribes single-channel 16 kHz [f32] samples like [`Model::transcribe_pcm`],
+    /// with a state from the pool, waiting for one to be returned first if they are
+    /// all in use.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of [f32] samples.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [Transcript]
+    pub fn transcribe_pcm(
+        &self,
+        audio: &[f32],
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        self.model
+            .transcribe_pcm_using(audio, options, Some(&self.states))
+    }
+
+    /// The pooled model. Methods called on it directly allocate a state of their own,
+    /// as with any [`Model`], rather than taking one from the pool.
+    #[must_use]
+    pub const fn model(&self) -> &'a Model {
+        self.model
+    }
+}
+
+/// The whisper states of a [`ModelPool`] that aren't in use, and a condition variable
+/// signalled whenever one is returned.
+pub(crate) struct StatePool<'a> {
+    states: Mutex<Vec<WhisperState<'a>>>,
+    returned: Condvar,
+}
+
+/// A whisper state, either allocated for a single use or taken from a [`ModelPool`],
+/// to which it's returned when dropped.
+pub(crate) struct State<'p, 'a> {
+    state: Option<WhisperState<'a>>,
+    pool: Option<&'p StatePool<'a>>,
+}
+
+impl Model {
+    /// Takes a state from `pool` if given, waiting until one is returned if they are
+    /// all in use, or allocates a new state otherwise.
+    pub(crate) fn state<'p, 'a>(
+        &'a self,
+        pool: Option<&'p StatePool<'a>>,
+    ) -> Result<State<'p, 'a>, ModelError> {
+        let Some(pool) = pool else {
+            let state = self
+                .context
+                .create_state()
+                .map_err(ModelError::WhisperError)?;
+            return Ok(State {
+                state: Some(state),
+                pool: None,
+            });
+        };
+
+        // A transcription that panicked still returned its state, so the states
+        // themselves are fine even if the lock was poisoned.
+        let mut states = pool.states.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(state) = states.pop() {
+                return Ok(State {
+                    state: Some(state),
+                    pool: Some(pool),
+                });
+            }
+            trace!("Waiting for a pooled whisper state");
+            states = pool
+                .returned
+                .wait(states)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+impl<'a> Deref for State<'_, 'a> {
+    type Target = WhisperState<'a>;
+
+    fn deref(&self) -> &WhisperState<'a> {
+        self.state
+            .as_ref()
+            .expect("state is only taken when dropped")
+    }
+}
+
+impl<'a> DerefMut for State<'_, 'a> {
+    fn deref_mut(&mut self) -> &mut WhisperState<'a> {
+        self.state
+            .as_mut()
+            .expect("state is only taken when dropped")
+    }
+}
+
+impl Drop for State<'_, '_> {
+    fn drop(&mut self) {
+        if let (Some(pool), Some(state)) = (self.pool, self.state.take()) {
+            pool.states
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(state);
+            pool.returned.notify_one();
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! EBU-STL (EBU Tech 3264) subtitles, the binary format broadcasters ask for.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::transcript::{self, to_millis, Transcript};
+
+/// Frame rate of the timecodes in EBU-STL subtitles. See [`Transcript::as_ebu_stl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StlFrameRate {
+    /// 25 frames per second, for PAL and most of the world's broadcasts.
+    Fps25,
+    /// 30 frames per second, for NTSC.
+    Fps30,
+}
+
+impl StlFrameRate {
+    const fn frames_per_second(self) -> i64 {
+        match self {
+            Self::Fps25 => 25,
+            Self::Fps30 => 30,
+        }
+    }
+
+    /// Disk format code in the GSI block.
+    const fn disk_format_code(self) -> &'static str {
+        match self {
+            Self::Fps25 => "STL25.01",
+            Self::Fps30 => "STL30.01",
+        }
+    }
+}
+
+/// Length of the General Subtitle Information block at the start of the file.
+const GSI_LEN: usize = 1024;
+/// Length of each Text and Timing Information block.
+const TTI_LEN: usize = 128;
+/// Length of the text field of a TTI block.
+const TEXT_FIELD_LEN: usize = 112;
+/// Most characters per row of text.
+const MAX_ROW_LEN: usize = 40;
+/// Line break in the text field.
+const CR_LF: u8 = 0x8a;
+/// Padding after the text in the text field.
+const UNUSED: u8 = 0x8f;
+/// Extension block number of the last, or only, TTI block of a subtitle.
+const LAST_EXTENSION_BLOCK: u8 = 0xff;
+/// Non-spacing diacritical marks, which precede the letter they go on.
+const DIACRITICAL_MARKS: std::ops::RangeInclusive<u8> = 0xc1..=0xcf;
+
+impl Transcript {
+    /// Returns the transcript as EBU-STL (EBU Tech 3264) subtitles, the binary format
+    /// most broadcasters require. Every utterance becomes a subtitle, centred at the
+    /// bottom of the screen and wrapped at 40 characters per row, with timecodes at
+    /// `frame_rate`. The text is encoded in the Latin alphabet (ISO 6937); characters
+    /// it can't represent are replaced by `?`.
+    ///
+    /// # Returns
+    /// The bytes of an `.stl` file.
+    #[must_use]
+    pub fn as_ebu_stl(&self, frame_rate: StlFrameRate) -> Vec<u8> {
+        let mut blocks = Vec::new();
+        let mut subtitles: u16 = 0;
+        // The GSI block repeats the time in of the first subtitle actually written.
+        let mut first_in_cue = None;
+        for utterance in transcript::in_time_order(&self.utterances) {
+            let text = encode_text(utterance.text.trim());
+            if text.is_empty() {
+                continue;
+            }
+            let time_in = timecode(utterance.start, frame_rate);
+            let time_out = timecode(utterance.stop, frame_rate);
+            first_in_cue.get_or_insert(time_in);
+            let chunks = text_fields(&text);
+            for (extension, chunk) in chunks.iter().enumerate() {
+                let mut block = [0; TTI_LEN];
+                block[1..3].copy_from_slice(&subtitles.to_le_bytes());
+                block[3] = if extension + 1 == chunks.len() {
+                    LAST_EXTENSION_BLOCK
+                } else {
+                    u8::try_from(extension).unwrap_or(LAST_EXTENSION_BLOCK - 1)
+                };
+                block[5..9].copy_from_slice(&time_in);
+                block[9..13].copy_from_slice(&time_out);
+                // Bottom of the screen, centred.
+                block[13] = 22;
+                block[14] = 2;
+                block[16..16 + chunk.len()].copy_from_slice(chunk);
+                block[16 + chunk.len()..].fill(UNUSED);
+                blocks.push(block);
+            }
+            subtitles = subtitles.wrapping_add(1);
+        }
+
+        let mut stl = general_subtitle_information(
+            frame_rate,
+            blocks.len(),
+            usize::from(subtitles),
+            first_in_cue.unwrap_or([0; 4]),
+        );
+        stl.extend(blocks.iter().flatten());
+        stl
+    }
+}
+
+/// Builds the GSI block. Text fields that mutter knows nothing about, such as the
+/// programme title, are left blank.
+fn general_subtitle_information(
+    frame_rate: StlFrameRate,
+    blocks: usize,
+    subtitles: usize,
+    first_in_cue: [u8; 4],
+) -> Vec<u8> {
+    let date = creation_date();
+    let [hours, minutes, seconds, frames] = first_in_cue;
+    let fields = [
+        // Code page, disk format, display standard (open subtitling), character code
+        // table (Latin) and language (unknown).
+        (0, "850".to_owned()),
+        (3, frame_rate.disk_format_code().to_owned()),
+        (11, "0".to_owned()),
+        (12, "00".to_owned()),
+        (14, "00".to_owned()),
+        // Creation and revision date, and revision number.
+        (224, date.clone()),
+        (230, date),
+        (236, "00".to_owned()),
+        // Number of TTI blocks, subtitles and subtitle groups.
+        (238, format!("{:05}", blocks.min(99_999))),
+        (243, format!("{:05}", subtitles.min(99_999))),
+        (248, "001".to_owned()),
+        // Maximum characters per row and rows.
+        (251, format!("{MAX_ROW_LEN:02}")),
+        (253, "23".to_owned()),
+        // Timecodes are intended for use, the programme starts at zero, and the first
+        // cue.
+        (255, "1".to_owned()),
+        (256, "00000000".to_owned()),
+        (
+            264,
+            format!("{hours:02}{minutes:02}{seconds:02}{frames:02}"),
+        ),
+        // Number of disks, and this disk's number.
+        (272, "1".to_owned()),
+        (273, "1".to_owned()),
+    ];
+    let mut gsi = vec![b' '; GSI_LEN];
+    for (offset, value) in fields {
+        gsi[offset..offset + value.len()].copy_from_slice(value.as_bytes());
+    }
+    gsi
+}
+
+/// Today's date as `YYMMDD`, in UTC.
+fn creation_date() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / 86_400);
+    // Howard Hinnant's civil_from_days, for days since 1970-01-01.
+    let days = i64::try_from(days).unwrap_or_default() + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:02}{month:02}{day:02}", year % 100)
+}
+
+/// Converts a raw whisper timestamp to the hours, minutes, seconds and frames of a
+/// TTI timecode.
+fn timecode(raw: i64, frame_rate: StlFrameRate) -> [u8; 4] {
+    let millis = to_millis(raw).max(0);
+    let fps = frame_rate.frames_per_second();
+    let frames = millis * fps / 1000;
+    let seconds = frames / fps;
+    let fields = [
+        (seconds / 3600).min(23),
+        seconds / 60 % 60,
+        seconds % 60,
+        frames % fps,
+    ];
+    fields.map(|field| u8::try_from(field).unwrap_or_default())
+}
+
+/// Splits encoded text into the text fields of consecutive TTI blocks, at most
+/// [`TEXT_FIELD_LEN`] bytes each, never between a diacritical mark and its letter.
+fn text_fields(text: &[u8]) -> Vec<&[u8]> {
+    let mut fields = Vec::new();
+    let mut rest = text;
+    while rest.len() > TEXT_FIELD_LEN {
+        let mut len = TEXT_FIELD_LEN;
+        if DIACRITICAL_MARKS.contains(&rest[len - 1]) {
+            len -= 1;
+        }
+        let (field, remainder) = rest.split_at(len);
+        fields.push(field);
+        rest = remainder;
+    }
+    fields.push(rest);
+    fields
+}
+
+/// Encodes text in ISO 6937, wrapped into rows of at most [`MAX_ROW_LEN`] characters
+/// separated by [`CR_LF`].
+fn encode_text(text: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(text.len());
+    let mut row_len = 0;
+    for word in text.split_whitespace() {
+        let word_len = word.chars().count();
+        if row_len > 0 && row_len + 1 + word_len > MAX_ROW_LEN {
+            encoded.push(CR_LF);
+            row_len = 0;
+        } else if row_len > 0 {
+            encoded.push(b' ');
+            row_len += 1;
+        }
+        for c in word.chars() {
+            encode_char(c, &mut encoded);
+        }
+        row_len += word_len;
+    }
+    encoded
+}
+
+/// Encodes a character in ISO 6937: printable ASCII as is, except for `$`, whose code
+/// is the currency sign there, and accented Latin letters as a non-spacing diacritical
+/// mark followed by the base letter.
+fn encode_char(c: char, encoded: &mut Vec<u8>) {
+    const GRAVE: u8 = 0xc1;
+    const ACUTE: u8 = 0xc2;
+    const CIRCUMFLEX: u8 = 0xc3;
+    const TILDE: u8 = 0xc4;
+    const DIAERESIS: u8 = 0xc8;
+    const RING: u8 = 0xca;
+    const CEDILLA: u8 = 0xcb;
+
+    if c == '$' {
+        encoded.push(0xa4);
+        return;
+    }
+    if c == ' ' || c.is_ascii_graphic() {
+        encoded.push(c as u8);
+        return;
+    }
+    let (mark, base) = match c {
+        'À' => (GRAVE, 'A'),
+        'Á' => (ACUTE, 'A'),
+        'Â' => (CIRCUMFLEX, 'A'),
+        'Ã' => (TILDE, 'A'),
+        'Ä' => (DIAERESIS, 'A'),
+        'Å' => (RING, 'A'),
+        'Ç' => (CEDILLA, 'C'),
+        'È' => (GRAVE, 'E'),
+        'É' => (ACUTE, 'E'),
+        'Ê' => (CIRCUMFLEX, 'E'),
+        'Ë' => (DIAERESIS, 'E'),
+        'Ì' => (GRAVE, 'I'),
+        'Í' => (ACUTE, 'I'),
+        'Î' => (CIRCUMFLEX, 'I'),
+        'Ï' => (DIAERESIS, 'I'),
+        'Ñ' => (TILDE, 'N'),
+        'Ò' => (GRAVE, 'O'),
+        'Ó' => (ACUTE, 'O'),
+        'Ô' => (CIRCUMFLEX, 'O'),
+        'Õ' => (TILDE, 'O'),
+        'Ö' => (DIAERESIS, 'O'),
+        'Ù' => (GRAVE, 'U'),
+        'Ú' => (ACUTE, 'U'),
+        'Û' => (CIRCUMFLEX, 'U'),
+        'Ü' => (DIAERESIS, 'U'),
+        'Ý' => (ACUTE, 'Y'),
+        'à' => (GRAVE, 'a'),
+        'á' => (ACUTE, 'a'),
+        'â' => (CIRCUMFLEX, 'a'),
+        'ã' => (TILDE, 'a'),
+        'ä' => (DIAERESIS, 'a'),
+        'å' => (RING, 'a'),
+        'ç' => (CEDILLA, 'c'),
+        'è' => (GRAVE, 'e'),
+        'é' => (ACUTE, 'e'),
+        'ê' => (CIRCUMFLEX, 'e'),
+        'ë' => (DIAERESIS, 'e'),
+        'ì' => (GRAVE, 'i'),
+        'í' => (ACUTE, 'i'),
+        'î' => (CIRCUMFLEX, 'i'),
+        'ï' => (DIAERESIS, 'i'),
+        'ñ' => (TILDE, 'n'),
+        'ò' => (GRAVE, 'o'),
+        'ó' => (ACUTE, 'o'),
+        'ô' => (CIRCUMFLEX, 'o'),
+        'õ' => (TILDE, 'o'),
+        'ö' => (DIAERESIS, 'o'),
+        'ù' => (GRAVE, 'u'),
+        'ú' => (ACUTE, 'u'),
+        'û' => (CIRCUMFLEX, 'u'),
+        'ü' => (DIAERESIS, 'u'),
+        'ý' => (ACUTE, 'y'),
+        'ÿ' => (DIAERESIS, 'y'),
+        'ß' => {
+            encoded.push(0xfb);
+            return;
+        }
+        _ => {
+            encoded.push(b'?');
+            return;
+        }
+    };
+    encoded.extend([mark, base as u8]);
+}
+
+#[test]
+fn test_as_ebu_stl() {
+    use crate::transcript::Utterance;
+
+    let long = "word ".repeat(30);
+    let transcript = Transcript {
+        utterances: vec![
+            Utterance {
+                start: 150,
+                stop: 302,
+                text: " Café au lait".to_owned(),
+                ..Utterance::default()
+            },
+            Utterance {
+                start: 400,
+                stop: 900,
+                text: long,
+                ..Utterance::default()
+            },
+        ],
+        ..Transcript::default()
+    };
+    let stl = transcript.as_ebu_stl(StlFrameRate::Fps25);
+    // Two subtitles, the second of which needs an extension block.
+    assert_eq!(stl.len(), GSI_LEN + 3 * TTI_LEN);
+    assert_eq!(&stl[3..11], b"STL25.01");
+    assert_eq!(&stl[238..248], b"0000300002");
+    assert_eq!(&stl[264..272], b"00000112");
+
+    let first = &stl[GSI_LEN..GSI_LEN + TTI_LEN];
+    assert_eq!(&first[1..4], &[0, 0, LAST_EXTENSION_BLOCK]);
+    assert_eq!(&first[5..13], &[0, 0, 1, 12, 0, 0, 3, 0]);
+    assert_eq!(&first[16..30], b"Caf\xc2e au lait\x8f");
+
+    let second = &stl[GSI_LEN + TTI_LEN..];
+    assert_eq!(&second[1..4], &[1, 0, 0]);
+    assert_eq!(
+        &second[TTI_LEN + 1..TTI_LEN + 4],
+        &[1, 0, LAST_EXTENSION_BLOCK]
+    );
+    assert_eq!(second[16 + 39], CR_LF);
+}
+
+#[test]
+fn test_as_ebu_stl_first_in_cue() {
+    use crate::transcript::Utterance;
+
+    let utterance = |start, stop, text: &str| Utterance {
+        start,
+        stop,
+        text: text.to_owned(),
+        ..Utterance::default()
+    };
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(400, 900, " Second"),
+            utterance(100, 150, " "),
+            utterance(150, 302, " First"),
+        ],
+        ..Transcript::default()
+    };
+    let stl = transcript.as_ebu_stl(StlFrameRate::Fps25);
+    assert_eq!(stl.len(), GSI_LEN + 2 * TTI_LEN);
+    // The empty utterance is skipped, so both start at 1.5 s.
+    assert_eq!(&stl[264..272], b"00000112");
+    let first = &stl[GSI_LEN..GSI_LEN + TTI_LEN];
+    assert_eq!(&first[5..9], &[0, 0, 1, 12]);
+    assert_eq!(&first[16..21], b"First");
+}
+
+#[test]
+fn test_text_fields() {
+    let mut text = vec![b'a'; TEXT_FIELD_LEN - 1];
+    encode_char('é', &mut text);
+    encode_char('$', &mut text);
+    let fields = text_fields(&text);
+    assert_eq!(fields.len(), 2);
+    assert_eq!(fields[0].len(), TEXT_FIELD_LEN - 1);
+    assert_eq!(fields[1], b"\xc2e\xa4");
+    assert_eq!(text_fields(b"short"), [b"short"]);
+}
+
+#[test]
+fn test_creation_date() {
+    let date = creation_date();
+    assert_eq!(date.len(), 6);
+    let month = date[2..4].parse::<u32>().unwrap();
+    let day = date[4..].parse::<u32>().unwrap();
+    assert!((1..=12).contains(&month));
+    assert!((1..=31).contains(&day));
+}
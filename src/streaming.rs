@@ -0,0 +1,259 @@
+use std::{
+    any::Any,
+    ffi::{c_int, c_void, CStr},
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    time::Duration,
+};
+
+use log::{trace, warn};
+use whisper_rs::whisper_rs_sys::{
+    whisper_context, whisper_full_get_segment_t0_from_state,
+    whisper_full_get_segment_t1_from_state, whisper_full_get_segment_text_from_state,
+    whisper_full_n_segments_from_state, whisper_state,
+};
+
+use crate::{
+    transcode,
+    transcript::{self, JsonlWriter, SrtWriter, Transcript, Utterance},
+    Model, ModelError, TranscribeOptions,
+};
+
+/// Iterator over the transcripts of consecutive windows of a live audio stream.
+/// Created by [`Model::transcribe_stream`].
+pub struct TranscriptStream<'a, I> {
+    model: &'a Model,
+    frames: I,
+    options: TranscribeOptions,
+    window_samples: usize,
+    buffer: Vec<f32>,
+    consumed_samples: usize,
+}
+
+impl<I: Iterator<Item = Vec<f32>>> Iterator for TranscriptStream<'_, I> {
+    type Item = Result<Transcript, ModelError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.len() < self.window_samples {
+            match self.frames.next() {
+                Some(frame) => self.buffer.extend(frame),
+                None if self.buffer.is_empty() => return None,
+                None => break,
+            }
+        }
+
+        let window_len = self.buffer.len().min(self.window_samples);
+        let window: Vec<f32> = self.buffer.drain(..window_len).collect();
+        trace!(
+            "Transcribing window of {window_len} samples at sample {}",
+            self.consumed_samples
+        );
+        let offset = transcode::samples_to_duration(self.consumed_samples);
+        self.consumed_samples += window_len;
+
+        Some(
+            self.model
+                .transcribe_pcm(&window, &self.options)
+                .map(|mut transcript| {
+                    transcript.offset_by(transcript::duration_to_raw(offset));
+                    transcript
+                }),
+        )
+    }
+}
+
+impl Model {
+    /// Transcribes a live stream of audio, given as frames of any size. Frames are
+    /// buffered until a window of `window_length` has filled up, which is then
+    /// transcribed. Each item of the returned iterator is the transcript of one window,
+    /// with timestamps relative to the start of the stream. When the frames run out,
+    /// the remaining audio is transcribed as a final, shorter window.
+    ///
+    /// # Arguments
+    /// - `frames`: Frames of audio, each an [f32] array of codec `pcm_s16le` in
+    ///   single-channel format.
+    /// - `window_length`: Length of audio to transcribe at a time. 30 seconds matches
+    ///   what whisper processes in one pass.
+    /// - `options`: [`TranscribeOptions`] to transcribe every window with.
+    /// # Errors
+    /// - [`ModelError::InvalidRange`], if `window_length` is zero.
+    /// # Returns
+    /// [`TranscriptStream`]
+    pub fn transcribe_stream<I: IntoIterator<Item = Vec<f32>>>(
+        &self,
+        frames: I,
+        window_length: Duration,
+        options: &TranscribeOptions,
+    ) -> Result<TranscriptStream<'_, I::IntoIter>, ModelError> {
+        let window_samples = transcode::duration_to_samples(window_length);
+        if window_samples == 0 {
+            return Err(ModelError::InvalidRange);
+        }
+        Ok(TranscriptStream {
+            model: self,
+            frames: frames.into_iter(),
+            options: options.clone(),
+            window_samples,
+            buffer: Vec::with_capacity(window_samples),
+            consumed_samples: 0,
+        })
+    }
+
+    /// Transcribes audio, writing every segment to `writer` as an SRT cue as soon as
+    /// whisper produces it, instead of waiting for the whole transcript. The audio must
+    /// be an [f32] float array of codec `pcm_s16le` and in single-channel format.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. Must be a [f32] array.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// - `writer`: Where to write the SRT cues, e.g. a [`std::fs::File`].
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::IoError`], if writing a cue failed. Transcription still runs
+    ///       to completion, but no further cues are written.
+    /// # Returns
+    /// The complete [Transcript], and the writer.
+    pub fn transcribe_pcm_to_srt<W: Write>(
+        &self,
+        audio: &[f32],
+        options: &TranscribeOptions,
+        writer: W,
+    ) -> Result<(Transcript, W), ModelError> {
+        let (transcript, writer) = self.transcribe_pcm_writing(
+            audio,
+            options,
+            SrtWriter::new(writer),
+            SrtWriter::write_utterance,
+        )?;
+        Ok((transcript, writer.into_inner()))
+    }
+
+    /// Transcribes audio, writing every segment to `writer` as a line of JSON, in the
+    /// format of [`Transcript::as_jsonl`], as soon as whisper produces it. Every line
+    /// is flushed right away, so this can feed a live transcript, e.g. to a browser
+    /// through server-sent events. The audio must be an [f32] float array of codec
+    /// `pcm_s16le` and in single-channel format.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. Must be a [f32] array.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// - `writer`: Where to write the lines, e.g. a [`std::net::TcpStream`].
+    /// # Errors
+    /// - [`ModelError`]
+    ///     - [`ModelError::IoError`], if writing a line failed. Transcription still
+    ///       runs to completion, but no further lines are written.
+    /// # Returns
+    /// The complete [Transcript], and the writer.
+    pub fn transcribe_pcm_to_jsonl<W: Write>(
+        &self,
+        audio: &[f32],
+        options: &TranscribeOptions,
+        writer: W,
+    ) -> Result<(Transcript, W), ModelError> {
+        let (transcript, writer) = self.transcribe_pcm_writing(
+            audio,
+            options,
+            JsonlWriter::new(writer),
+            JsonlWriter::write_utterance,
+        )?;
+        Ok((transcript, writer.into_inner()))
+    }
+
+    /// Transcribes audio, calling `write` with `sink` and every segment as soon as
+    /// whisper produces it. Once a write fails, no further segments are written.
+    fn transcribe_pcm_writing<S>(
+        &self,
+        audio: &[f32],
+        options: &TranscribeOptions,
+        sink: S,
+        write: fn(&mut S, &Utterance) -> io::Result<()>,
+    ) -> Result<(Transcript, S), ModelError> {
+        let mut params = self.full_params(options)?;
+        let mut segments = Segments {
+            sink,
+            write,
+            error: None,
+            panic: None,
+        };
+        // SAFETY: whisper.cpp calls the callback on this thread, from within `full`,
+        // which `run_full` is done with before it returns, so `segments` outlives every
+        // call and isn't touched by anything else in the meantime. The callback only
+        // reads the state it is given, and doesn't touch the context.
+        unsafe {
+            params.set_new_segment_callback(Some(new_segments::<S>));
+            params.set_new_segment_callback_user_data(std::ptr::addr_of_mut!(segments).cast());
+        }
+
+        let transcript = self.run_full(params, options.limit(audio), options, None);
+        if let Some(payload) = segments.panic {
+            panic::resume_unwind(payload);
+        }
+        let transcript = transcript?;
+        match segments.error {
+            Some(e) => Err(ModelError::IoError(e)),
+            None => Ok((transcript, segments.sink)),
+        }
+    }
+}
+
+/// Where the segment callback writes segments to, and what went wrong doing so.
+struct Segments<S> {
+    sink: S,
+    write: fn(&mut S, &Utterance) -> io::Result<()>,
+    /// The first error writing a segment. No segments are written after it.
+    error: Option<io::Error>,
+    /// A panic while writing a segment, resumed once whisper is done, as it can't
+    /// unwind through whisper.cpp.
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+/// Segment callback for whisper.cpp, writing the `n_new` latest segments of `state` to
+/// the [`Segments`] that `user_data` points to.
+unsafe extern "C" fn new_segments<S>(
+    _context: *mut whisper_context,
+    state: *mut whisper_state,
+    n_new: c_int,
+    user_data: *mut c_void,
+) {
+    // SAFETY: `user_data` is the `Segments<S>` set up in `transcribe_pcm_writing`, which
+    // nothing else uses while whisper runs.
+    let segments = unsafe { &mut *user_data.cast::<Segments<S>>() };
+    if segments.error.is_some() || segments.panic.is_some() {
+        return;
+    }
+    // SAFETY: whisper.cpp hands over a valid state, and the segments up to its count.
+    let n_segments = unsafe { whisper_full_n_segments_from_state(state) };
+    for segment in (n_segments - n_new).max(0)..n_segments {
+        trace!("Writing segment {segment}");
+        // SAFETY: as above; the text is null terminated and lives as long as the state.
+        let utterance = unsafe {
+            let start = whisper_full_get_segment_t0_from_state(state, segment);
+            let text = whisper_full_get_segment_text_from_state(state, segment);
+            Utterance {
+                start,
+                stop: whisper_full_get_segment_t1_from_state(state, segment),
+                text: if text.is_null() {
+                    String::new()
+                } else {
+                    CStr::from_ptr(text).to_string_lossy().into_owned()
+                },
+                seek: Some(transcript::window_start(start)),
+                ..Utterance::default()
+            }
+        };
+        match panic::catch_unwind(AssertUnwindSafe(|| {
+            (segments.write)(&mut segments.sink, &utterance)
+        })) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                warn!("Failed to write segment {segment}: {e}");
+                segments.error = Some(e);
+                return;
+            }
+            Err(payload) => {
+                segments.panic = Some(payload);
+                return;
+            }
+        }
+    }
+}
@@ -1,12 +1,24 @@
 // ModelType tests
 #[cfg(test)]
 use {
-    crate::{Model, ModelType},
+    crate::{Model, ModelError, ModelType, Priority, TranscribeOptions},
     audrey::hound::WavReader,
     std::io::Cursor,
     strum::IntoEnumIterator,
 };
 
+#[test]
+fn test_model_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Model>();
+    assert_send_sync::<crate::ModelPool<'static>>();
+}
+
+#[test]
+fn test_model_type_all() {
+    assert_eq!(ModelType::all(), ModelType::iter().collect::<Vec<_>>());
+}
+
 #[test]
 fn test_model_urls() {
     for model in ModelType::iter() {
@@ -37,10 +49,10 @@ fn test_transcribe() {
     let samples: Result<Vec<i16>, _> = reader.samples().collect();
     let samples = samples.unwrap();
     let mut output = vec![0.0f32; samples.len()];
-    let _audio = whisper_rs::convert_integer_to_float_audio(&samples, &mut output).unwrap();
+    whisper_rs::convert_integer_to_float_audio(&samples, &mut output).unwrap();
 
     let transcription = model
-        .transcribe_pcm_s16le(&output, false, false, None)
+        .transcribe_pcm_s16le(&output, false, false, None, None, None)
         .unwrap();
     assert!(transcription.as_text().contains("country"));
 }
@@ -51,7 +63,731 @@ fn test_transcribe_with_transcode() {
     let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
 
     let transcription = model
-        .transcribe_audio(kliks_mp3, false, false, None)
+        .transcribe_audio(kliks_mp3, false, false, None, None, None)
         .unwrap();
     assert!(transcription.as_text().contains("Valve"));
 }
+
+#[test]
+fn test_transcribe_range() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let transcript = model
+        .transcribe_range(
+            jfk_wav,
+            std::time::Duration::from_secs(2),
+            std::time::Duration::from_secs(6),
+            &TranscribeOptions::default(),
+        )
+        .unwrap();
+    assert!(transcript
+        .utterances
+        .iter()
+        .all(|utterance| (200..=600).contains(&utterance.start)));
+    assert_eq!(transcript.audio_duration.unwrap().as_secs(), 11);
+    assert!(transcript.clipping_ratio.is_some());
+    let format = transcript.source_format.unwrap();
+    assert_eq!((format.sample_rate, format.channels), (16000, 1));
+}
+
+#[test]
+fn test_languages() {
+    let languages = Model::languages();
+    assert!(languages.contains(&"en"));
+    assert!(languages.contains(&"de"));
+    assert!(Model::is_valid_language("fr"));
+    assert!(!Model::is_valid_language("english"));
+}
+
+#[test]
+fn test_language_enum_matches_whisper() {
+    use strum::IntoEnumIterator;
+
+    let codes = crate::Language::iter()
+        .map(crate::Language::as_code)
+        .collect::<Vec<_>>();
+    assert_eq!(codes, Model::languages());
+}
+
+#[test]
+fn test_transcribe_in() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let options = TranscribeOptions::default().with_language("de");
+    let transcript = model
+        .transcribe_in(jfk_wav, Some(crate::Language::English), &options)
+        .unwrap();
+    assert!(transcript.as_text().contains("country"));
+}
+
+#[test]
+fn test_transcribe_invalid_language() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let result =
+        model.transcribe_pcm_s16le(&vec![0.0; 16000], false, false, None, Some("english"), None);
+    assert!(matches!(result, Err(ModelError::InvalidLanguage(_))));
+}
+
+#[test]
+fn test_thread_count() {
+    assert!(matches!(
+        crate::thread_count(Some(0)),
+        Err(ModelError::InvalidThreadCount)
+    ));
+    assert_eq!(crate::thread_count(Some(3)).unwrap(), 3);
+    assert!(crate::thread_count(None).unwrap() >= 1);
+}
+
+#[test]
+fn test_transcribe_zero_threads() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let options = TranscribeOptions::default().with_threads(0);
+    let result = model.transcribe_pcm(&vec![0.0; 16000], &options);
+    assert!(matches!(result, Err(ModelError::InvalidThreadCount)));
+}
+
+#[test]
+fn test_recommend() {
+    assert_eq!(
+        ModelType::recommend(true, Priority::Accuracy, None),
+        ModelType::MediumEn
+    );
+    assert_eq!(
+        ModelType::recommend(false, Priority::Accuracy, Some(1000)),
+        ModelType::Small
+    );
+    assert_eq!(
+        ModelType::recommend(false, Priority::Speed, Some(100)),
+        ModelType::Tiny
+    );
+}
+
+#[test]
+fn test_estimate_duration() {
+    let hour = std::time::Duration::from_secs(60 * 60);
+    let tiny = Model::estimate_duration(hour, &ModelType::TinyEn);
+    let large = Model::estimate_duration(hour, &ModelType::LargeV3);
+    assert!(tiny < hour);
+    assert!(tiny < large);
+    assert_eq!(
+        Model::estimate_duration(std::time::Duration::ZERO, &ModelType::Medium),
+        std::time::Duration::ZERO
+    );
+}
+
+#[test]
+fn test_transcribe_short_clip() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let output = wav_to_pcm(include_bytes!("../samples/jfk.wav"));
+
+    let options = TranscribeOptions {
+        audio_ctx: Some(128),
+        ..TranscribeOptions::dictation()
+    };
+    let transcript = model.transcribe_pcm(&output[..8000], &options).unwrap();
+    assert_eq!(
+        transcript.audio_duration,
+        Some(std::time::Duration::from_millis(500))
+    );
+    // Half a second of audio, padded to the second whisper.cpp needs.
+    let padded_end = crate::transcript::duration_to_raw(std::time::Duration::from_secs(1));
+    for utterance in &transcript.utterances {
+        assert!(0 <= utterance.start && utterance.start <= utterance.stop);
+        assert!(utterance.stop <= padded_end);
+    }
+}
+
+#[test]
+fn test_benchmark() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let output = wav_to_pcm(include_bytes!("../samples/jfk.wav"));
+
+    let benchmark = model
+        .benchmark(&output, &TranscribeOptions::dictation())
+        .unwrap();
+    assert_eq!(benchmark.segments, 1);
+    assert!(benchmark.real_time_factor().is_some());
+}
+
+#[test]
+fn test_transcribe_pcm_i16() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+
+    let mut reader = WavReader::new(Cursor::new(jfk_wav)).unwrap();
+    let samples: Result<Vec<i16>, _> = reader.samples().collect();
+    let samples = samples.unwrap();
+
+    let transcription = model
+        .transcribe_pcm_i16(&samples, &TranscribeOptions::default())
+        .unwrap();
+    assert!(transcription.as_text().contains("country"));
+}
+
+#[test]
+fn test_detect_language() {
+    let model = Model::download(&ModelType::Tiny).unwrap();
+    let output = wav_to_pcm(include_bytes!("../samples/jfk.wav"));
+
+    let detected = model.detect_language(&output, None).unwrap();
+    assert_eq!(detected.language, "en");
+    assert!(detected.probability > 0.5);
+    assert_eq!(
+        model.detect_language_above(&output, 1.1, None).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_download_cancellable() {
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::{Duration, Instant},
+    };
+
+    let agent = ureq::agent();
+    let abort = AtomicBool::new(true);
+    let result = Model::download_cancellable(&ModelType::TinyEn, &agent, &abort, None);
+    assert!(matches!(result, Err(ModelError::Cancelled)));
+
+    abort.store(false, Ordering::Relaxed);
+    let deadline = Instant::now() + Duration::from_millis(500);
+    let result = Model::download_cancellable(&ModelType::LargeV3, &agent, &abort, Some(deadline));
+    assert!(matches!(result, Err(ModelError::Cancelled)));
+    assert!(deadline.elapsed() < Duration::from_secs(5));
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(Duration::from_millis(500));
+            abort.store(true, Ordering::Relaxed);
+        });
+        let result = Model::download_cancellable(&ModelType::LargeV3, &agent, &abort, None);
+        assert!(matches!(result, Err(ModelError::Cancelled)));
+    });
+}
+
+#[test]
+fn test_load_or_download() {
+    let cache_dir = std::env::temp_dir().join("mutter-test-load-or-download");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    std::fs::create_dir_all(&cache_dir).unwrap();
+    // A truncated download should be replaced rather than fail to load.
+    let path = cache_dir.join(ModelType::TinyEn.file_name());
+    std::fs::write(&path, b"lmgg").unwrap();
+
+    Model::load_or_download(&ModelType::TinyEn, &cache_dir).unwrap();
+    assert!(std::fs::metadata(&path).unwrap().len() > 77_600_000);
+    Model::load_or_download(&ModelType::TinyEn, &cache_dir).unwrap();
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+#[test]
+fn test_new_with_cpu_fallback() {
+    let cache_dir = std::env::temp_dir().join("mutter-test-new-with-cpu-fallback");
+    Model::load_or_download(&ModelType::TinyEn, &cache_dir).unwrap();
+    let path = cache_dir.join(ModelType::TinyEn.file_name());
+    let path = path.to_str().unwrap();
+
+    let mut params = crate::WhisperContextParameters::default();
+    params.use_gpu(true);
+    let model = Model::new_with_cpu_fallback(path, params).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let transcript = model
+        .transcribe(jfk_wav, &TranscribeOptions::default())
+        .unwrap();
+    assert!(transcript.as_text().contains("country"));
+
+    let mut params = crate::WhisperContextParameters::default();
+    params.use_gpu(true);
+    assert!(Model::new_with_cpu_fallback("missing.bin", params).is_err());
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+#[test]
+fn test_transcribe_deterministic() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
+
+    let options = TranscribeOptions::deterministic();
+    let first = model.transcribe(kliks_mp3, &options).unwrap();
+    let second = model.transcribe(kliks_mp3, &options).unwrap();
+    assert_eq!(first.as_text(), second.as_text());
+}
+
+#[test]
+fn test_avg_logprob() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
+
+    let transcript = model
+        .transcribe(kliks_mp3, &TranscribeOptions::default())
+        .unwrap();
+    for utterance in &transcript.utterances {
+        let avg_logprob = utterance.avg_logprob.unwrap();
+        assert!(avg_logprob <= 0.0);
+    }
+}
+
+#[test]
+fn test_transcribe_in_pieces() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
+
+    let options = TranscribeOptions {
+        no_context: false,
+        chunk_longer_than: Some(std::time::Duration::from_secs(5)),
+        ..TranscribeOptions::default()
+    };
+    let transcript = model.transcribe(kliks_mp3, &options).unwrap();
+    assert!(transcript.as_text().contains("Valve"));
+    assert!(transcript
+        .utterances
+        .windows(2)
+        .all(|pair| pair[0].start <= pair[1].start));
+}
+
+#[test]
+fn test_transcribe_pipelined() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
+
+    let transcript = model
+        .transcribe_pipelined(
+            kliks_mp3,
+            std::time::Duration::from_secs(5),
+            &TranscribeOptions::default(),
+        )
+        .unwrap();
+    assert!(transcript.as_text().contains("Valve"));
+    assert!(transcript.audio_duration.unwrap() > std::time::Duration::from_secs(5));
+}
+
+#[test]
+fn test_default_options_match_transcribe_audio() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
+
+    let legacy = model
+        .transcribe_audio(kliks_mp3, false, false, None, None, Some(2))
+        .unwrap();
+    let options = TranscribeOptions::default().with_threads(2);
+    let transcript = model.transcribe(kliks_mp3, &options).unwrap();
+    assert_eq!(legacy.as_srt(), transcript.as_srt());
+}
+
+#[test]
+fn test_model_type_from_header() {
+    let header = |n_vocab: u32, layers: u32, n_mels: u32| {
+        let fields = [
+            0x6767_6d6c,
+            n_vocab,
+            1500,
+            384,
+            6,
+            layers,
+            448,
+            384,
+            6,
+            layers,
+            n_mels,
+            1,
+        ];
+        let mut header = [0; 48];
+        for (bytes, field) in header.chunks_exact_mut(4).zip(fields) {
+            bytes.copy_from_slice(&field.to_le_bytes());
+        }
+        header
+    };
+    assert_eq!(
+        ModelType::from_header(&header(51864, 4, 80)),
+        Some(ModelType::TinyEn)
+    );
+    assert_eq!(
+        ModelType::from_header(&header(51865, 12, 80)),
+        Some(ModelType::Small)
+    );
+    assert_eq!(
+        ModelType::from_header(&header(51866, 32, 128)),
+        Some(ModelType::LargeV3)
+    );
+    assert_eq!(ModelType::from_header(&header(51865, 5, 80)), None);
+    assert_eq!(ModelType::from_header(&[0; 48]), None);
+    assert_eq!(ModelType::from_file("samples/jfk.wav"), None);
+}
+
+#[test]
+fn test_n_mels() {
+    assert_eq!(ModelType::LargeV3.n_mels(), 128);
+    assert_eq!(ModelType::LargeV2.n_mels(), 80);
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    assert_eq!(model.n_mels(), ModelType::TinyEn.n_mels());
+}
+
+#[test]
+fn test_translate_english_only() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    assert!(!model.supports_translation());
+    let options = TranscribeOptions::default().with_translate(true);
+    assert!(matches!(
+        model.transcribe_pcm(&vec![0.0; 16000], &options),
+        Err(ModelError::TranslationUnsupported)
+    ));
+    assert!(Model::download(&ModelType::Tiny)
+        .unwrap()
+        .supports_translation());
+}
+
+#[test]
+fn test_context_sizes() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    assert_eq!(model.max_audio_ctx(), 1500);
+    assert_eq!(model.n_text_ctx(), 448);
+}
+
+#[test]
+fn test_probe_duration() {
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let duration = crate::probe_duration(jfk_wav).unwrap();
+    assert_eq!(duration.as_secs(), 11);
+    let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
+    assert!(crate::probe_duration(kliks_mp3).unwrap() > std::time::Duration::from_secs(5));
+    assert!(matches!(
+        crate::probe_duration(b"not audio"),
+        Err(ModelError::UnsupportedFormat)
+    ));
+}
+
+#[test]
+fn test_decode_errors() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let options = TranscribeOptions::default();
+    assert!(matches!(
+        model.transcribe(b"not audio", &options),
+        Err(ModelError::UnsupportedFormat)
+    ));
+    // A WAV header with no samples after it.
+    assert!(matches!(
+        model.transcribe(wav_i16(&[]), &options),
+        Err(ModelError::EmptyStream)
+    ));
+}
+
+#[test]
+fn test_transcribe_raw_pcm() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let data = jfk_wav.windows(4).position(|id| id == b"data").unwrap() + 8;
+    let transcript = model
+        .transcribe_raw_pcm(
+            &jfk_wav[data..],
+            16000,
+            1,
+            crate::PcmFormat::S16Le,
+            &TranscribeOptions::default(),
+        )
+        .unwrap();
+    assert!(transcript.as_text().contains("country"));
+    assert_eq!(transcript.audio_duration.unwrap().as_secs(), 11);
+}
+
+#[test]
+fn test_pad_and_trim() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let (_, mut samples) = model
+        .transcribe_with_samples(jfk_wav, &TranscribeOptions::default())
+        .unwrap();
+    samples.resize(samples.len() + 16000 * 5, 0.0);
+    let options = TranscribeOptions {
+        pad_to: Some(std::time::Duration::from_secs(20)),
+        trim_trailing_silence: Some(crate::EnergyVad::default()),
+        ..TranscribeOptions::default()
+    };
+    let transcript = model.transcribe_pcm(&samples, &options).unwrap();
+    assert!(transcript.as_text().contains("country"));
+    assert!(transcript.utterances.last().unwrap().stop <= 1200);
+    let speech_ranges = transcript.speech_ranges.unwrap();
+    assert!(!speech_ranges.is_empty());
+    assert!(speech_ranges.last().unwrap().1 <= std::time::Duration::from_secs(12));
+}
+
+#[test]
+fn test_transcribe_prepared() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let prepared = Model::prepare_audio(jfk_wav).unwrap();
+    assert_eq!(prepared.duration().as_secs(), 11);
+    for prompt in ["", "Ask not"] {
+        let options = TranscribeOptions::default().with_initial_prompt(prompt);
+        let transcript = model.transcribe_prepared(&prepared, &options).unwrap();
+        assert!(transcript.as_text().contains("country"));
+        assert_eq!(transcript.audio_duration, Some(prepared.duration()));
+    }
+}
+
+#[test]
+fn test_source_format() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let transcript = model
+        .transcribe(jfk_wav, &TranscribeOptions::default())
+        .unwrap();
+    let format = transcript.source_format.unwrap();
+    assert_eq!((format.sample_rate, format.channels), (16000, 1));
+
+    let stereo = vec![0.0; 44100 * 2 * 2];
+    let transcript = model
+        .transcribe_interleaved(&stereo, 2, 44100, &TranscribeOptions::default())
+        .unwrap();
+    let format = transcript.source_format.unwrap();
+    assert_eq!((format.sample_rate, format.channels), (44100, 2));
+    assert_eq!(format.frame_at(100), 44100);
+}
+
+#[test]
+fn test_max_transcribe_duration() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let options = TranscribeOptions {
+        max_transcribe_duration: Some(std::time::Duration::from_secs(3)),
+        ..TranscribeOptions::default()
+    };
+    let transcript = model.transcribe(jfk_wav, &options).unwrap();
+    assert!(!transcript.as_text().contains("country"));
+    assert!(transcript
+        .utterances
+        .iter()
+        .all(|utterance| utterance.stop <= 300));
+    assert_eq!(transcript.audio_duration.unwrap().as_secs(), 11);
+}
+
+#[test]
+fn test_decode_up_to() {
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let max_duration = std::time::Duration::from_secs(3);
+    let decoded = crate::transcode::decode_up_to(jfk_wav.to_vec(), Some(max_duration)).unwrap();
+    let max_samples = crate::transcode::duration_to_samples(max_duration);
+    assert!(decoded.samples.len().abs_diff(max_samples) <= 1);
+    assert_eq!(decoded.duration.as_secs(), 11);
+}
+
+#[test]
+fn test_transcribe_pcm_at() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let output = wav_to_pcm(include_bytes!("../samples/jfk.wav"));
+    let doubled = output
+        .iter()
+        .flat_map(|&sample| [sample, sample])
+        .collect::<Vec<_>>();
+
+    let transcript = model
+        .transcribe_pcm_at(&doubled, 32000, &TranscribeOptions::default())
+        .unwrap();
+    assert!(transcript.as_text().contains("country"));
+    assert_eq!(transcript.audio_duration.unwrap().as_secs(), 11);
+}
+
+#[test]
+fn test_transcribe_code_switched() {
+    let model = Model::download(&ModelType::Tiny).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+
+    let transcript = model
+        .transcribe_code_switched(
+            jfk_wav,
+            std::time::Duration::from_secs(6),
+            &TranscribeOptions::default(),
+        )
+        .unwrap();
+    assert!(transcript.as_text().contains("country"));
+    assert!(transcript
+        .utterances
+        .iter()
+        .all(|utterance| utterance.language.as_deref() == Some("en")));
+}
+
+#[test]
+fn test_transcribe_with_samples() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
+
+    let (transcript, samples) = model
+        .transcribe_with_samples(kliks_mp3, &TranscribeOptions::default())
+        .unwrap();
+    assert!(transcript.as_text().contains("Valve"));
+    let heard = crate::transcode::samples_to_duration(samples.len());
+    let duration = transcript.audio_duration.unwrap();
+    assert!(heard.abs_diff(duration) < std::time::Duration::from_millis(100));
+}
+
+#[test]
+fn test_transcribe_cached() {
+    let cache_dir = std::env::temp_dir().join("mutter-test-transcribe-cached");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
+
+    let options = TranscribeOptions::default();
+    let first = model
+        .transcribe_cached(kliks_mp3, &options, &cache_dir)
+        .unwrap();
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 1);
+    let second = model
+        .transcribe_cached(kliks_mp3, &options, &cache_dir)
+        .unwrap();
+    assert_eq!(first.as_srt(), second.as_srt());
+    assert_eq!(first.processing_time, second.processing_time);
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+#[test]
+fn test_transcribe_pcm_to_jsonl() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    let (_, samples) = model
+        .transcribe_with_samples(jfk_wav, &TranscribeOptions::default())
+        .unwrap();
+
+    let (transcript, written) = model
+        .transcribe_pcm_to_jsonl(&samples, &TranscribeOptions::default(), Vec::new())
+        .unwrap();
+    let written = String::from_utf8(written).unwrap();
+    assert_eq!(written.lines().count(), transcript.utterances.len());
+    assert!(written.contains("country"));
+}
+
+#[test]
+fn test_model_pool() {
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let pool = crate::ModelPool::new(&model, std::num::NonZeroUsize::new(2).unwrap()).unwrap();
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..4)
+            .map(|_| scope.spawn(|| pool.transcribe(jfk_wav, &TranscribeOptions::default())))
+            .collect();
+        for handle in handles {
+            let transcript = handle.join().unwrap().unwrap();
+            assert!(transcript.as_text().contains("country"));
+        }
+    });
+}
+
+#[test]
+fn test_clipping_ratio() {
+    let jfk_wav = include_bytes!("../samples/jfk.wav");
+    assert!(crate::clipping_ratio(jfk_wav).unwrap() < crate::CLIPPING_WARN_RATIO);
+    assert!(matches!(
+        crate::clipping_ratio(b"not audio"),
+        Err(ModelError::UnsupportedFormat)
+    ));
+
+    // An i16 source, through the decoder.
+    let mut samples = vec![8000i16; 16000];
+    samples[..2000].fill(i16::MAX);
+    samples[2000..4000].fill(i16::MIN);
+    let ratio = crate::clipping_ratio(wav_i16(&samples)).unwrap();
+    assert!((ratio - 0.25).abs() < 1e-6);
+
+    // An f32 source, through interleaved samples.
+    let mut samples = vec![0.25f32; 16000];
+    samples[..4000].fill(1.0);
+    let decoded = crate::transcode::decode_interleaved(samples, 1, 16000).unwrap();
+    assert!((decoded.clipping_ratio - 0.25).abs() < 1e-6);
+}
+
+/// A 16 kHz single-channel 16-bit WAV file of `samples`.
+#[cfg(test)]
+fn wav_i16(samples: &[i16]) -> Vec<u8> {
+    let data_len = u32::try_from(samples.len() * 2).unwrap();
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    for field in [16u32, 0x0001_0001, 16000, 32000, 0x0010_0002] {
+        wav.extend_from_slice(&field.to_le_bytes());
+    }
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+/// The samples of a 16-bit WAV file, converted to [f32].
+#[cfg(test)]
+fn wav_to_pcm(wav: &[u8]) -> Vec<f32> {
+    let samples = WavReader::new(Cursor::new(wav))
+        .unwrap()
+        .samples()
+        .collect::<Result<Vec<i16>, _>>()
+        .unwrap();
+    let mut output = vec![0.0f32; samples.len()];
+    whisper_rs::convert_integer_to_float_audio(&samples, &mut output).unwrap();
+    output
+}
+
+#[test]
+fn test_load_or_download_concurrently() {
+    let cache_dir = std::env::temp_dir().join("mutter-test-load-or-download-concurrently");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    std::thread::scope(|scope| {
+        let loads = (0..3)
+            .map(|_| scope.spawn(|| Model::load_or_download(&ModelType::TinyEn, &cache_dir)))
+            .collect::<Vec<_>>();
+        for load in loads {
+            load.join().unwrap().unwrap();
+        }
+    });
+    let path = cache_dir.join(ModelType::TinyEn.file_name());
+    assert!(std::fs::metadata(&path).unwrap().len() > 77_600_000);
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+#[test]
+fn test_transcribe_batch() {
+    let dir = std::env::temp_dir().join("mutter-test-transcribe-batch");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let jfk_path = dir.join("jfk.wav");
+    let kliks_path = dir.join("3kliks-cut.mp3");
+    std::fs::write(&jfk_path, include_bytes!("../samples/jfk.wav")).unwrap();
+    std::fs::write(&kliks_path, include_bytes!("../samples/3kliks-cut.mp3")).unwrap();
+    let manifest = dir.join("manifest.txt");
+    let model = Model::download(&ModelType::TinyEn).unwrap();
+    let options = TranscribeOptions::default();
+
+    // The second file fails to be stored, as if the process crashed.
+    let mut texts = Vec::new();
+    let result = model.transcribe_batch(
+        [&jfk_path, &kliks_path],
+        &options,
+        &manifest,
+        |path, transcript| {
+            if path == kliks_path {
+                return Err(ModelError::IoError(std::io::ErrorKind::Other.into()));
+            }
+            texts.push(transcript.as_text());
+            Ok(())
+        },
+    );
+    assert!(matches!(result, Err(ModelError::IoError(_))));
+    assert!(texts[0].contains("country"));
+
+    let mut resumed = Vec::new();
+    let transcribed = model
+        .transcribe_batch([&jfk_path, &kliks_path], &options, &manifest, |path, _| {
+            resumed.push(path.to_owned());
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(transcribed, 1);
+    assert_eq!(resumed, std::slice::from_ref(&kliks_path));
+
+    let transcribed = model
+        .transcribe_batch([&jfk_path, &kliks_path], &options, &manifest, |_, _| Ok(()))
+        .unwrap();
+    assert_eq!(transcribed, 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
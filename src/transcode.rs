@@ -1,21 +1,670 @@
-use rodio::{source::UniformSourceIterator, Decoder, Source};
-use std::io::Cursor;
+use rodio::{
+    buffer::SamplesBuffer, cpal::FromSample, decoder::DecoderError, source::UniformSourceIterator,
+    Decoder, Sample, Source,
+};
+use std::{borrow::Cow, cell::Cell, io::Cursor, sync::mpsc::SyncSender, time::Duration};
 
-use crate::ModelError;
+use log::{debug, log_enabled, warn, Level};
+use symphonia::core::{
+    formats::FormatOptions,
+    io::{MediaSourceStream, MediaSourceStreamOptions},
+    meta::MetadataOptions,
+    probe::Hint,
+    units::TimeBase,
+};
+
+use crate::{
+    options::Preprocessor,
+    transcript::{SourceFormat, Transcript},
+    vad::EnergyVad,
+    DecodeOptions, ModelError,
+};
+
+/// Sample rate whisper expects its input in, in Hz.
+pub const SAMPLE_RATE: u32 = 16000;
+
+/// Magnitude at or above which a sample counts as clipped. Slightly below 1.0, as
+/// integer formats can't reach +1.0 exactly.
+const CLIPPING_LEVEL: f32 = 0.999;
+
+/// Share of clipped samples above which decoding logs a warning. Clean recordings
+/// clip on well under one sample in a thousand.
+pub const CLIPPING_WARN_RATIO: f32 = 0.001;
+
+/// Decoded, single-channel, 16 kHz audio
+#[derive(Debug, Clone)]
+pub(crate) struct Decoded {
+    /// Samples to feed whisper
+    pub samples: Vec<f32>,
+    /// Duration of the original audio, measured before resampling
+    pub duration: Duration,
+    /// Share of the original samples at full scale, see [`clipping_ratio`]
+    pub clipping_ratio: f32,
+    /// Sample rate and channels of the original audio
+    pub source_format: SourceFormat,
+}
+
+impl Decoded {
+    /// Record what is known about the original audio in a transcript of it
+    pub fn annotate(&self, transcript: &mut Transcript) {
+        transcript.audio_duration = Some(self.duration);
+        transcript.clipping_ratio = Some(self.clipping_ratio);
+        transcript.source_format = Some(self.source_format);
+    }
+}
+
+/// Audio decoded once, to be transcribed any number of times without decoding it
+/// again, e.g. while trying out prompts. Created by [`Model::prepare_audio`] and
+/// transcribed with [`Model::transcribe_prepared`].
+///
+/// [`Model::prepare_audio`]: crate::Model::prepare_audio
+/// [`Model::transcribe_prepared`]: crate::Model::transcribe_prepared
+#[derive(Debug, Clone)]
+pub struct PreparedAudio {
+    pub(crate) decoded: Decoded,
+}
+
+impl PreparedAudio {
+    /// The audio as whisper hears it: single-channel [f32] samples at
+    /// [`SAMPLE_RATE`], between -1.0 and 1.0, after the speech filters.
+    #[must_use]
+    pub fn samples(&self) -> &[f32] {
+        &self.decoded.samples
+    }
+
+    /// Duration of the original audio, measured before resampling.
+    #[must_use]
+    pub const fn duration(&self) -> Duration {
+        self.decoded.duration
+    }
+
+    /// Sample rate and channels of the original audio.
+    #[must_use]
+    pub const fn source_format(&self) -> SourceFormat {
+        self.decoded.source_format
+    }
+}
 
 /// Decode a byte array of audio into a float array
-pub fn decode(bytes: Vec<u8>) -> Result<Vec<f32>, ModelError> {
-    let input = Cursor::new(bytes);
-    let source = Decoder::new(input).unwrap();
-    let output_sample_rate = 16000;
-    let channels = 1;
-    // Resample to output sample rate and channels
-    let resample = UniformSourceIterator::new(source, channels, output_sample_rate);
-    // High and low pass filters to enhance the audio
-    let pass_filter = resample.low_pass(3000).high_pass(200).convert_samples();
-    let samples: Vec<i16> = pass_filter.collect::<Vec<i16>>();
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(bytes = bytes.len())))]
+pub fn decode(bytes: Vec<u8>) -> Result<Decoded, ModelError> {
+    let source = decoder(bytes)?;
+    non_empty(resample(source, None)?)
+}
+
+/// Decode a byte array of audio into a float array, stopping after `max_duration` of
+/// it if given, for [`TranscribeOptions::max_transcribe_duration`]. The duration is
+/// still that of the whole audio if the container records it.
+///
+/// [`TranscribeOptions::max_transcribe_duration`]: crate::TranscribeOptions::max_transcribe_duration
+pub fn decode_up_to(bytes: Vec<u8>, max_duration: Option<Duration>) -> Result<Decoded, ModelError> {
+    decode_with(bytes, &DecodeOptions::default(), max_duration)
+}
+
+/// Open a byte array of audio with rodio, telling apart audio in a format it doesn't
+/// support, audio without any track, and damaged audio
+fn decoder(bytes: Vec<u8>) -> Result<Decoder<Cursor<Vec<u8>>>, ModelError> {
+    Decoder::new(Cursor::new(bytes)).map_err(|e| match e {
+        DecoderError::UnrecognizedFormat => ModelError::UnsupportedFormat,
+        DecoderError::NoStreams => ModelError::EmptyStream,
+        DecoderError::IoError(e) => ModelError::CorruptData(e),
+        DecoderError::DecodeError(e) | DecoderError::LimitError(e) => {
+            ModelError::CorruptData(e.to_owned())
+        }
+        DecoderError::ResetRequired => ModelError::CorruptData("decoder reset required".into()),
+    })
+}
+
+/// Decoded audio, or [`ModelError::EmptyStream`] if there is none
+fn non_empty(decoded: Decoded) -> Result<Decoded, ModelError> {
+    if decoded.samples.is_empty() {
+        return Err(ModelError::EmptyStream);
+    }
+    Ok(decoded)
+}
+
+/// Measure the duration of a byte array of audio without resampling or filtering it.
+/// The length is read from the container without decoding any audio where it
+/// records one: through symphonia's probe for the formats it's built with, which
+/// gives the number of frames of the track, and from the headers of WAV and FLAC
+/// files. A decode is unavoidable when the container doesn't record its length,
+/// such as Ogg Vorbis; the samples are then decoded and counted, which is still much
+/// faster than a full decode.
+///
+/// # Errors
+/// - [`ModelError::UnsupportedFormat`], if the audio isn't in a format rodio supports.
+/// - [`ModelError::CorruptData`], if the audio is damaged or truncated.
+pub fn probe_duration(bytes: impl AsRef<[u8]>) -> Result<Duration, ModelError> {
+    let bytes = bytes.as_ref().to_vec();
+    if let Some(duration) = probe_container_duration(bytes.clone()) {
+        return Ok(duration);
+    }
+    let source = decoder(bytes)?;
+    if let Some(duration) = source.total_duration() {
+        return Ok(duration);
+    }
+    let samples_per_second = u128::from(source.sample_rate()) * u128::from(source.channels());
+    let samples = u64::try_from(source.count()).unwrap_or(u64::MAX);
+    Ok(original_duration(samples, samples_per_second))
+}
+
+/// Duration of the default track as recorded in the container, from the number of
+/// frames symphonia's probe finds and the track's time base. `None` if symphonia
+/// doesn't recognise the format, or the container doesn't record the length.
+fn probe_container_duration(bytes: Vec<u8>) -> Option<Duration> {
+    let stream = MediaSourceStream::new(
+        Box::new(Cursor::new(bytes)),
+        MediaSourceStreamOptions::default(),
+    );
+    let probed = symphonia::default::get_probe()
+        .format(
+            &Hint::new(),
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?;
+    let params = &probed.format.default_track()?.codec_params;
+    let n_frames = params.n_frames?;
+    let time_base = params
+        .time_base
+        .filter(|time_base| time_base.numer > 0 && time_base.denom > 0)
+        .or_else(|| {
+            let rate = params.sample_rate.filter(|&rate| rate > 0)?;
+            Some(TimeBase::new(1, rate))
+        })?;
+    let time = time_base.calc_time(n_frames);
+    Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+}
+
+/// Measure the share of samples in a byte array of audio that are at full scale,
+/// counting every channel, from 0.0 to 1.0. Audio that was recorded too loud, such
+/// as many phone recordings, is clipped: its peaks are cut off flat, which distorts
+/// it and makes whisper transcribe it poorly. Anything above [`CLIPPING_WARN_RATIO`]
+/// is worth warning about before transcribing.
+///
+/// # Errors
+/// - [`ModelError::UnsupportedFormat`], if the audio isn't in a format rodio supports.
+/// - [`ModelError::CorruptData`], if the audio is damaged or truncated.
+pub fn clipping_ratio(bytes: impl AsRef<[u8]>) -> Result<f32, ModelError> {
+    let source = decoder(bytes.as_ref().to_vec())?;
+    let (samples, clipped) = source.fold((0, 0), |(samples, clipped), sample| {
+        (samples + 1, clipped + u64::from(is_clipped(sample)))
+    });
+    Ok(ratio(clipped, samples))
+}
+
+/// Decode a byte array of audio into a float array, applying the [`DecodeOptions`]
+/// preprocessing stages, and stopping after `max_duration` of it if given, see
+/// [`decode_up_to`]
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(bytes = bytes.len())))]
+pub fn decode_with(
+    bytes: Vec<u8>,
+    options: &DecodeOptions,
+    max_duration: Option<Duration>,
+) -> Result<Decoded, ModelError> {
+    let decoded = match max_duration {
+        Some(max_duration) => {
+            let source = decoder(bytes.clone())?;
+            // Only the start is decoded, so the samples can't tell how long it all is.
+            let duration = source
+                .total_duration()
+                .or_else(|| probe_container_duration(bytes));
+            let mut decoded = resample(source.take_duration(max_duration), options.preprocess)?;
+            decoded.duration = duration.unwrap_or(decoded.duration);
+            decoded
+        }
+        None => resample(decoder(bytes)?, options.preprocess)?,
+    };
+    let mut decoded = non_empty(decoded)?;
+    if let Some(denoise) = options.denoise {
+        decoded.samples = denoise(&decoded.samples);
+    }
+    Ok(decoded)
+}
+
+/// Denoise 16 kHz samples with nnnoiseless, which only works on 48 kHz audio in the
+/// range of an i16: upsample by linear interpolation, denoise, then take every third
+/// sample
+#[cfg(feature = "nnnoiseless")]
+pub fn denoise_nnnoiseless(samples: &[f32]) -> Vec<f32> {
+    use nnnoiseless::DenoiseState;
+
+    const RATIO: usize = 3;
+    let scale = f32::from(i16::MAX);
+    let mut upsampled = Vec::with_capacity(samples.len() * RATIO);
+    for (idx, &sample) in samples.iter().enumerate() {
+        let next = samples.get(idx + 1).copied().unwrap_or(sample);
+        for step in 0..RATIO {
+            #[allow(clippy::cast_precision_loss)]
+            let weight = step as f32 / RATIO as f32;
+            upsampled.push((sample + (next - sample) * weight) * scale);
+        }
+    }
+
+    let mut state = DenoiseState::new();
+    let mut denoised = Vec::with_capacity(upsampled.len());
+    let mut output = [0.0; DenoiseState::FRAME_SIZE];
+    for frame in upsampled.chunks(DenoiseState::FRAME_SIZE) {
+        let mut input = [0.0; DenoiseState::FRAME_SIZE];
+        input[..frame.len()].copy_from_slice(frame);
+        state.process_frame(&mut output, &input);
+        denoised.extend_from_slice(&output[..frame.len()]);
+    }
+    denoised
+        .iter()
+        .step_by(RATIO)
+        .map(|sample| (sample / scale).clamp(-1.0, 1.0))
+        .collect()
+}
+
+/// Convert interleaved float samples with any number of channels and sample rate into
+/// the single-channel, 16 kHz float array whisper expects.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip(samples), fields(samples = samples.len()))
+)]
+pub fn decode_interleaved(
+    samples: Vec<f32>,
+    channels: u16,
+    sample_rate: u32,
+) -> Result<Decoded, ModelError> {
+    if channels == 0 || sample_rate == 0 {
+        return Err(ModelError::UnsupportedFormat);
+    }
+    resample(SamplesBuffer::new(channels, sample_rate, samples), None)
+}
+
+/// Sample format of raw, headerless PCM audio. See [`Model::transcribe_raw_pcm`].
+///
+/// [`Model::transcribe_raw_pcm`]: crate::Model::transcribe_raw_pcm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmFormat {
+    /// Unsigned 8-bit samples, centred on 128.
+    U8,
+    /// Signed 16-bit little-endian samples, `s16le`. The most common raw format.
+    S16Le,
+    /// Signed 16-bit big-endian samples, `s16be`.
+    S16Be,
+    /// Signed 32-bit little-endian samples, `s32le`.
+    S32Le,
+    /// 32-bit little-endian float samples between -1.0 and 1.0, `f32le`.
+    F32Le,
+}
+
+impl PcmFormat {
+    /// Number of bytes in one sample of one channel.
+    #[must_use]
+    pub const fn bytes_per_sample(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::S16Le | Self::S16Be => 2,
+            Self::S32Le | Self::F32Le => 4,
+        }
+    }
+
+    /// Converts one sample, [`PcmFormat::bytes_per_sample`] bytes long, to a float
+    /// between -1.0 and 1.0
+    #[allow(clippy::cast_precision_loss)]
+    fn to_f32(self, bytes: &[u8]) -> f32 {
+        match self {
+            Self::U8 => (f32::from(bytes[0]) - 128.0) / 128.0,
+            Self::S16Le => f32::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32768.0,
+            Self::S16Be => f32::from(i16::from_be_bytes([bytes[0], bytes[1]])) / 32768.0,
+            Self::S32Le => {
+                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32
+                    / 2_147_483_648.0
+            }
+            Self::F32Le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+/// Convert raw, headerless PCM audio with any number of channels and sample rate into
+/// the single-channel, 16 kHz float array whisper expects. A frame cut short at the
+/// end, as when a capture is interrupted, is dropped.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(bytes), fields(bytes = bytes.len())))]
+pub fn decode_raw_pcm(
+    bytes: &[u8],
+    format: PcmFormat,
+    channels: u16,
+    sample_rate: u32,
+) -> Result<Decoded, ModelError> {
+    if channels == 0 || sample_rate == 0 {
+        return Err(ModelError::UnsupportedFormat);
+    }
+    let frame_len = format.bytes_per_sample() * usize::from(channels);
+    let frames = bytes.chunks_exact(frame_len);
+    if !frames.remainder().is_empty() {
+        warn!(
+            "Dropping {} bytes of raw PCM that don't make up a whole frame",
+            frames.remainder().len()
+        );
+    }
+    let samples = frames
+        .flat_map(|frame| frame.chunks_exact(format.bytes_per_sample()))
+        .map(|sample| format.to_f32(sample))
+        .collect();
+    decode_interleaved(samples, channels, sample_rate)
+}
+
+/// Resample, downmix and filter any source into a float array. If given, `preprocess`
+/// runs on the resampled audio before it is downmixed
+fn resample<S>(source: S, preprocess: Option<Preprocessor>) -> Result<Decoded, ModelError>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    let output_sample_rate = SAMPLE_RATE;
+    let input_channels = source.channels();
+    let source_format = SourceFormat {
+        sample_rate: source.sample_rate(),
+        channels: input_channels,
+    };
+    let samples_per_second = u128::from(source.sample_rate()) * u128::from(input_channels);
+    let original_samples = Cell::new(0);
+    let clipped_samples = Cell::new(0);
+    let source = Counted {
+        source,
+        samples: &original_samples,
+        clipped: &clipped_samples,
+    };
+    let samples: Vec<i16> = match preprocess {
+        Some(preprocess) => {
+            // Resample, keeping every channel for the preprocessing stage
+            let resample: UniformSourceIterator<_, f32> =
+                UniformSourceIterator::new(source, input_channels, output_sample_rate);
+            preprocess_and_downmix(&resample.collect::<Vec<_>>(), input_channels, preprocess)
+        }
+        None => downmix_and_filter(source).collect(),
+    };
     let mut output: Vec<f32> = vec![0.0f32; samples.len()];
     let result: Result<(), whisper_rs::WhisperError> =
         whisper_rs::convert_integer_to_float_audio(&samples, &mut output);
-    result.map(|()| output).map_err(ModelError::WhisperError)
+    result.map_err(ModelError::WhisperError)?;
+    let clipping_ratio = ratio(clipped_samples.get(), original_samples.get());
+    if clipping_ratio > CLIPPING_WARN_RATIO {
+        warn!(
+            "{:.2}% of the input audio is clipped, expect a poor transcription",
+            clipping_ratio * 100.0
+        );
+    }
+    Ok(Decoded {
+        samples: output,
+        duration: original_duration(original_samples.get(), samples_per_second),
+        clipping_ratio,
+        source_format,
+    })
+}
+
+/// Run `preprocess` on resampled audio that still has all its channels, then downmix
+/// and filter it. Kept apart from [`resample`], whose bound on the source's samples
+/// would otherwise stand in for the one on these [f32] samples
+fn preprocess_and_downmix(samples: &[f32], channels: u16, preprocess: Preprocessor) -> Vec<i16> {
+    let preprocessed = preprocess(samples, channels);
+    let buffer = SamplesBuffer::new(channels, SAMPLE_RATE, preprocessed);
+    downmix_and_filter(buffer).collect()
+}
+
+/// Decode a byte array of audio like [`decode`], but send the samples to `blocks` as
+/// soon as they are decoded, `block_samples` at a time, so they can be transcribed
+/// while the rest is still being decoded. Stops early if the receiver hangs up.
+/// Returns the duration of the original audio
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(bytes = bytes.len())))]
+pub fn decode_blocks(
+    bytes: Vec<u8>,
+    block_samples: usize,
+    blocks: &SyncSender<Vec<f32>>,
+) -> Result<Duration, ModelError> {
+    let source = decoder(bytes)?;
+    let samples_per_second = u128::from(source.sample_rate()) * u128::from(source.channels());
+    let original_samples = Cell::new(0);
+    let clipped_samples = Cell::new(0);
+    let source = Counted {
+        source,
+        samples: &original_samples,
+        clipped: &clipped_samples,
+    };
+    let mut samples = downmix_and_filter(source);
+    loop {
+        let block = samples.by_ref().take(block_samples).collect::<Vec<i16>>();
+        if block.is_empty() {
+            break;
+        }
+        let mut output = vec![0.0f32; block.len()];
+        whisper_rs::convert_integer_to_float_audio(&block, &mut output)
+            .map_err(ModelError::WhisperError)?;
+        if blocks.send(output).is_err() {
+            break;
+        }
+    }
+    Ok(original_duration(
+        original_samples.get(),
+        samples_per_second,
+    ))
+}
+
+/// Duration of `samples` samples of the original audio, counting every channel
+fn original_duration(samples: u64, samples_per_second: u128) -> Duration {
+    let nanos = u128::from(samples) * 1_000_000_000 / samples_per_second.max(1);
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+/// Whether a sample of the original audio is at full scale
+fn is_clipped<S>(sample: S) -> bool
+where
+    f32: FromSample<S>,
+{
+    f32::from_sample_(sample).abs() >= CLIPPING_LEVEL
+}
+
+/// Share of `samples` that are `clipped`, zero for empty audio
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+fn ratio(clipped: u64, samples: u64) -> f32 {
+    if samples == 0 {
+        return 0.0;
+    }
+    (clipped as f64 / samples as f64) as f32
+}
+
+/// Downmix any source to a single channel at [`SAMPLE_RATE`], and filter it to enhance
+/// speech
+fn downmix_and_filter<S>(source: S) -> impl Iterator<Item = i16>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    let channels = 1;
+    // Resample to output sample rate and channels
+    let resample = UniformSourceIterator::new(source, channels, SAMPLE_RATE);
+    // High and low pass filters to enhance the audio
+    resample.low_pass(3000).high_pass(200).convert_samples()
+}
+
+/// Source that counts the samples read from it, to measure the duration of the
+/// original audio rather than that of the resampled audio, which drifts on long files,
+/// and the samples at full scale, before resampling smooths them over
+struct Counted<'a, S> {
+    source: S,
+    samples: &'a Cell<u64>,
+    clipped: &'a Cell<u64>,
+}
+
+impl<S> Iterator for Counted<'_, S>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.source.next()?;
+        self.samples.set(self.samples.get() + 1);
+        if is_clipped(sample) {
+            self.clipped.set(self.clipped.get() + 1);
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S> Source for Counted<'_, S>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+/// Pad audio shorter than one second with trailing silence, as whisper.cpp refuses to
+/// transcribe anything shorter
+pub fn pad_to_min_length(samples: &[f32]) -> Cow<'_, [f32]> {
+    pad_to(samples, Duration::from_secs(1))
+}
+
+/// Pad audio shorter than `min_length` with trailing silence
+pub fn pad_to(samples: &[f32], min_length: Duration) -> Cow<'_, [f32]> {
+    let min_length = duration_to_samples(min_length);
+    if samples.len() >= min_length {
+        return Cow::Borrowed(samples);
+    }
+    let mut padded = samples.to_vec();
+    padded.resize(min_length, 0.0);
+    Cow::Owned(padded)
+}
+
+/// Number of samples at [`SAMPLE_RATE`] covering the given duration
+pub fn duration_to_samples(duration: Duration) -> usize {
+    usize::try_from(duration.as_millis() * u128::from(SAMPLE_RATE) / 1000).unwrap_or(usize::MAX)
+}
+
+/// Duration of the given number of samples at [`SAMPLE_RATE`]
+pub fn samples_to_duration(samples: usize) -> Duration {
+    let micros = samples as u128 * 1_000_000 / u128::from(SAMPLE_RATE);
+    Duration::from_micros(u64::try_from(micros).unwrap_or(u64::MAX))
+}
+
+/// Range of the median zero-crossing rate, in crossings per sample, of speech at
+/// [`SAMPLE_RATE`]. Speech recorded at 44.1 or 48 kHz but passed as 16 kHz plays back
+/// about three times slower, and crosses zero about three times less often, falling
+/// below it; 8 kHz speech passed as 16 kHz plays back twice as fast, rising above it.
+const SPEECH_ZERO_CROSSING_RATE: std::ops::RangeInclusive<f32> = 0.02..=0.3;
+
+/// Fewest frames of speech [`check_sample_rate`] judges the sample rate on.
+const MIN_SPEECH_FRAMES: usize = 10;
+
+/// Logs a debug message if samples passed in as [`SAMPLE_RATE`] don't sound like
+/// speech at that rate, as a hint that the caller skipped resampling. Only a rough
+/// guess from how often the speech in it crosses zero, so it's never an error; does
+/// nothing unless debug logging is enabled.
+pub(crate) fn check_sample_rate(samples: &[f32]) {
+    if !log_enabled!(Level::Debug) {
+        return;
+    }
+    let Some(rate) = median_zero_crossing_rate(samples) else {
+        return;
+    };
+    if rate < *SPEECH_ZERO_CROSSING_RATE.start() {
+        debug!(
+            "Audio looks like it was recorded at a higher sample rate than {SAMPLE_RATE} Hz \
+             (median zero-crossing rate {rate:.3}); resample it, e.g. with \
+             Model::transcribe_pcm_at"
+        );
+    } else if rate > *SPEECH_ZERO_CROSSING_RATE.end() {
+        debug!(
+            "Audio looks like it was recorded at a lower sample rate than {SAMPLE_RATE} Hz \
+             (median zero-crossing rate {rate:.3}); resample it, e.g. with \
+             Model::transcribe_pcm_at"
+        );
+    }
+}
+
+/// Median over the frames of speech in `samples` of the fraction of samples at which
+/// the signal changes sign. `None` if there are too few frames of speech to tell.
+fn median_zero_crossing_rate(samples: &[f32]) -> Option<f32> {
+    let vad = EnergyVad::default();
+    let frame_samples = duration_to_samples(vad.frame_length).max(2);
+    #[allow(clippy::cast_precision_loss)]
+    let mut rates = samples
+        .chunks_exact(frame_samples)
+        .filter(|frame| vad.is_speech(frame))
+        .map(|frame| {
+            let crossings = frame
+                .windows(2)
+                .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+                .count();
+            crossings as f32 / (frame.len() - 1) as f32
+        })
+        .collect::<Vec<_>>();
+    if rates.len() < MIN_SPEECH_FRAMES {
+        return None;
+    }
+    let middle = rates.len() / 2;
+    Some(*rates.select_nth_unstable_by(middle, f32::total_cmp).1)
+}
+
+#[test]
+fn test_median_zero_crossing_rate() {
+    #[allow(clippy::cast_precision_loss)]
+    let tone = |hz: f32| {
+        (0..SAMPLE_RATE)
+            .map(|i| 0.5 * (std::f32::consts::TAU * hz * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect::<Vec<_>>()
+    };
+    // Two crossings per period.
+    let rate = median_zero_crossing_rate(&tone(1000.0)).unwrap();
+    assert!((rate - 0.125).abs() < 0.01, "{rate}");
+    assert!(SPEECH_ZERO_CROSSING_RATE.contains(&rate));
+    assert!(median_zero_crossing_rate(&tone(100.0)).unwrap() < *SPEECH_ZERO_CROSSING_RATE.start());
+    assert!(median_zero_crossing_rate(&tone(3000.0)).unwrap() > *SPEECH_ZERO_CROSSING_RATE.end());
+    assert_eq!(median_zero_crossing_rate(&vec![0.0; 16000]), None);
+}
+
+#[test]
+fn test_is_clipped() {
+    assert!(is_clipped(i16::MAX) && is_clipped(i16::MIN));
+    assert!(!is_clipped(i16::MAX / 2) && !is_clipped(0i16));
+    assert!(is_clipped(1.0f32) && is_clipped(-1.0f32));
+    assert!(!is_clipped(0.5f32) && !is_clipped(0.0f32));
+}
+
+#[test]
+fn test_raw_pcm_samples() {
+    assert!((PcmFormat::U8.to_f32(&[0]) + 1.0).abs() < f32::EPSILON);
+    assert!(PcmFormat::U8.to_f32(&[128]).abs() < f32::EPSILON);
+    let half = 0x4000_i16;
+    assert!((PcmFormat::S16Le.to_f32(&half.to_le_bytes()) - 0.5).abs() < f32::EPSILON);
+    assert!((PcmFormat::S16Be.to_f32(&half.to_be_bytes()) - 0.5).abs() < f32::EPSILON);
+    assert!((PcmFormat::S32Le.to_f32(&i32::MIN.to_le_bytes()) + 1.0).abs() < f32::EPSILON);
+    assert!((PcmFormat::F32Le.to_f32(&(-0.25f32).to_le_bytes()) + 0.25).abs() < f32::EPSILON);
+    assert_eq!(PcmFormat::S32Le.bytes_per_sample(), 4);
+}
+
+#[test]
+fn test_probe_container_duration() {
+    let kliks_mp3 = include_bytes!("../samples/3kliks-cut.mp3");
+    let duration = probe_container_duration(kliks_mp3.to_vec()).unwrap();
+    assert!(duration > Duration::from_secs(5));
+    assert_eq!(probe_container_duration(b"not audio".to_vec()), None);
 }
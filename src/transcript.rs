@@ -1,93 +1,1484 @@
+use flate2::{write::ZlibEncoder, Compression};
 use num::integer::div_floor;
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+    time::Duration,
+};
 
 pub use crate::Model;
+use crate::{formatter::TranscriptFormatter, ModelError};
 
 // Repurposed from https://github.com/m1guelpf/whisper-cli-rs/. Fixed numerous bugs/typos
 
+/// Whisper gives timestamps in centiseconds (hundredths of a second), not milliseconds.
+/// Multiply a raw timestamp, such as [`Utterance::start`], by this to get milliseconds.
+pub const WHISPER_TIMESTAMP_SCALE: i64 = 10;
+
+/// Length of the window of audio whisper decodes at once, in centiseconds.
+const WINDOW_LENGTH: i64 = 3000;
+
+/// Start of the 30 second window on a fixed grid that contains the raw timestamp
+/// `raw`, for [`Utterance::seek`].
+pub(crate) fn window_start(raw: i64) -> i64 {
+    div_floor(raw, WINDOW_LENGTH) * WINDOW_LENGTH
+}
+
+/// Converts a raw whisper timestamp, such as [`Utterance::start`], to milliseconds.
+#[must_use]
+pub const fn to_millis(raw: i64) -> i64 {
+    raw * WHISPER_TIMESTAMP_SCALE
+}
+
+/// Ratio of the length of `text` to its length once compressed with zlib. `None` for
+/// empty text.
+pub(crate) fn compression_ratio(text: &str) -> Option<f32> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes()).ok()?;
+    let compressed = encoder.finish().ok()?;
+    #[allow(clippy::cast_precision_loss)]
+    Some(text.len() as f32 / compressed.len() as f32)
+}
+
+/// Converts a raw whisper timestamp, such as [`Utterance::start`], to a presentation
+/// timestamp in units of `1 / timescale` seconds, e.g. a timescale of 90000 for
+/// MPEG-TS and most MP4 video tracks. Rounds to the nearest unit.
+#[must_use]
+pub fn to_pts(raw: i64, timescale: u32) -> i64 {
+    let centiseconds_per_second = 1000 / i128::from(WHISPER_TIMESTAMP_SCALE);
+    let pts = (i128::from(raw) * i128::from(timescale) * 2 + centiseconds_per_second)
+        / (centiseconds_per_second * 2);
+    i64::try_from(pts).unwrap_or(i64::MAX)
+}
+
+/// Converts a raw whisper timestamp to a [`Duration`]. Negative timestamps are
+/// treated as zero.
+pub(crate) fn raw_to_duration(raw: i64) -> Duration {
+    Duration::from_millis(u64::try_from(to_millis(raw)).unwrap_or_default())
+}
+
+/// Converts a [`Duration`] to a raw whisper timestamp.
+pub(crate) fn duration_to_raw(duration: Duration) -> i64 {
+    i64::try_from(duration.as_millis()).unwrap_or(i64::MAX) / WHISPER_TIMESTAMP_SCALE
+}
+
+/// Merges tokens, such as [`Transcript::word_utterances`], into whole words. A token
+/// starting with a space starts a new word; others are appended to the previous one.
+/// The probability of a word is that of its least likely token.
+pub(crate) fn merge_tokens_into_words<'a>(
+    tokens: impl IntoIterator<Item = &'a Utterance>,
+) -> Vec<Utterance> {
+    let mut words: Vec<Utterance> = Vec::new();
+    for token in tokens
+        .into_iter()
+        .filter(|token| !token.text.starts_with("[_"))
+    {
+        match words.last_mut() {
+            Some(word) if !token.text.starts_with(' ') => {
+                word.text.push_str(&token.text);
+                word.stop = word.stop.max(token.stop);
+                word.probability = word
+                    .probability
+                    .zip(token.probability)
+                    .map(|(word, token)| word.min(token));
+            }
+            _ => words.push(token.clone()),
+        }
+    }
+    words
+}
+
+/// The utterances sorted by start, then stop, keeping the order of utterances with the
+/// same times. See [`Transcript::sort_by_time`].
+pub(crate) fn in_time_order(utterances: &[Utterance]) -> Vec<&Utterance> {
+    let mut sorted = utterances.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|utterance| (utterance.start, utterance.stop));
+    sorted
+}
+
+/// Joins the bytes of consecutive tokens until they form valid UTF-8. whisper's
+/// byte-level tokens can split a multi-byte character, common in e.g. Japanese, across
+/// tokens, and decoding each token on its own would leave replacement characters in
+/// both halves. A joined token spans all its parts, and its probability is that of its
+/// least likely part.
+#[derive(Debug, Default)]
+pub(crate) struct TokenAssembler {
+    bytes: Vec<u8>,
+    start: i64,
+    stop: i64,
+    probability: f32,
+}
+
+impl TokenAssembler {
+    /// Adds a token. Returns it, joined with any incomplete tokens before it, once its
+    /// bytes end in a complete character.
+    pub(crate) fn push(
+        &mut self,
+        bytes: &[u8],
+        start: i64,
+        stop: i64,
+        probability: f32,
+    ) -> Option<Utterance> {
+        if self.bytes.is_empty() {
+            self.start = start;
+            self.probability = probability;
+        } else {
+            self.probability = self.probability.min(probability);
+        }
+        self.bytes.extend_from_slice(bytes);
+        self.stop = stop;
+        match std::str::from_utf8(&self.bytes) {
+            // Only wait for more bytes if the invalid sequence is an incomplete
+            // character at the end; anything else will never become valid.
+            Err(e) if e.error_len().is_none() => None,
+            _ => self.finish(),
+        }
+    }
+
+    /// Returns whatever is left, with any incomplete character replaced, e.g. at the
+    /// end of a segment.
+    pub(crate) fn finish(&mut self) -> Option<Utterance> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&self.bytes).into_owned();
+        self.bytes.clear();
+        Some(Utterance {
+            text,
+            start: self.start,
+            stop: self.stop,
+            probability: Some(self.probability),
+            ..Utterance::default()
+        })
+    }
+}
+
 /// Transcript of an audio.
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Transcription failures are always reported as a [`ModelError`], never as an empty
+/// transcript. A transcript without any speech, see [`Transcript::is_empty`], means
+/// whisper ran successfully but recognised no speech, e.g. in silence, noise or music.
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Transcript {
     /// Duration that it took to transcribe the audio.
     pub processing_time: Duration,
     /// List of utterances in the transcript - split by normal segments.
+    /// In time order when transcribed, but not necessarily once edited or pieced
+    /// together by hand; see [`Transcript::sort_by_time`]. Subtitles, labels and cues
+    /// are always output in time order.
     pub utterances: Vec<Utterance>,
     /// List of words in the transcript - split by each word.
     /// Only present if `word_timestamps` is `true` in [`Model::transcribe_audio`].
     pub word_utterances: Option<Vec<Utterance>>,
+    /// Breakdown of the time spent in each phase of the transcription.
+    #[serde(default)]
+    pub timings: Timings,
+    /// Duration of the audio that was transcribed. When transcribing a file or
+    /// interleaved samples, this is measured on the original audio, before resampling,
+    /// so it doesn't drift on long files.
+    pub audio_duration: Option<Duration>,
+    /// Share of the original samples at full scale, from 0.0 to 1.0, when transcribing
+    /// a file or interleaved samples. See [`clipping_ratio`](crate::clipping_ratio).
+    #[serde(default)]
+    pub clipping_ratio: Option<f32>,
+    /// Sample rate and channels of the original audio, before it was downmixed and
+    /// resampled to [`SAMPLE_RATE`](crate::SAMPLE_RATE), when transcribing a file or
+    /// interleaved samples.
+    #[serde(default)]
+    pub source_format: Option<SourceFormat>,
+    /// Stretches of speech found by the [`EnergyVad`](crate::EnergyVad) in
+    /// [`TranscribeOptions::trim_trailing_silence`](crate::TranscribeOptions), as start
+    /// and end times, from the same pass over the audio as the transcript. Anything
+    /// between them is silence, e.g. dead air to cut from the audio. Silences shorter
+    /// than [`TRIM_MARGIN`](crate::vad::TRIM_MARGIN) count as speech. `None` unless
+    /// that option is set.
+    #[serde(default)]
+    pub speech_ranges: Option<Vec<(Duration, Duration)>>,
+}
+
+/// Sample rate and channels of audio as it was before whisper got it. Downmixing and
+/// resampling don't shift anything in time, so a timestamp in a transcript is the same
+/// moment in the original audio; [`SourceFormat::frame_at`] finds its sample there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFormat {
+    /// Sample rate, in Hz.
+    pub sample_rate: u32,
+    /// Number of channels.
+    pub channels: u16,
+}
+
+impl SourceFormat {
+    /// Index of the frame, i.e. the sample of each channel, of the original audio at
+    /// a raw whisper timestamp, such as [`Utterance::start`]. Multiply by
+    /// [`SourceFormat::channels`] for the index of its first sample in interleaved
+    /// audio.
+    #[must_use]
+    pub fn frame_at(&self, raw: i64) -> u64 {
+        let frame = i128::from(to_millis(raw.max(0))) * i128::from(self.sample_rate) / 1000;
+        u64::try_from(frame).unwrap_or(u64::MAX)
+    }
+}
+
+/// Time spent in each phase of a transcription.
+///
+/// whisper-rs doesn't expose whisper.cpp's internal timings, so the time spent in the
+/// encoder and the decoder can't be told apart; both are part of `inference`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timings {
+    /// Decoding and resampling the input audio. Zero if samples were passed in directly.
+    pub decode: Duration,
+    /// Running whisper: computing the spectrogram, encoding and decoding.
+    pub inference: Duration,
+    /// Reading the segments and words back out of whisper.
+    pub extraction: Duration,
+}
+
+/// Result of [`Model::benchmark`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Benchmark {
+    /// Running whisper: computing the spectrogram, encoding and decoding.
+    pub inference: Duration,
+    /// Number of segments whisper produced.
+    pub segments: usize,
+    /// Duration of the audio whisper was run over.
+    pub audio_duration: Duration,
+}
+
+impl Benchmark {
+    /// Real-time factor: the time spent on inference divided by the duration of the
+    /// audio. Below 1 is faster than real time. `None` for empty audio.
+    #[must_use]
+    pub fn real_time_factor(&self) -> Option<f32> {
+        (!self.audio_duration.is_zero())
+            .then(|| self.inference.as_secs_f32() / self.audio_duration.as_secs_f32())
+    }
+
+    /// Estimates how long running whisper over `audio_len` of audio will take with the
+    /// same model, options and hardware, assuming inference time grows linearly with
+    /// the duration of the audio. Benchmark at least a few seconds of audio: whisper
+    /// always processes 30 second windows, so tiny clips overestimate. `None` if the
+    /// benchmark was run over empty audio.
+    #[must_use]
+    pub fn estimate_duration(&self, audio_len: Duration) -> Option<Duration> {
+        self.real_time_factor()
+            .map(|real_time_factor| audio_len.mul_f32(real_time_factor))
+    }
+}
+
+/// Confidence of the segments of a transcript, for monitoring transcription quality.
+/// Returned by [`Transcript::confidence_stats`].
+///
+/// The confidence of a segment is the geometric mean of the probabilities of its
+/// tokens, `exp(avg_logprob)`, from 0 to 1. Clear speech usually scores above 0.6;
+/// transcripts with many segments below 0.4 are worth reviewing.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceStats {
+    /// Number of segments with a confidence.
+    pub segments: usize,
+    /// Confidence of the least confident segment.
+    pub min: f32,
+    /// Confidence of the most confident segment.
+    pub max: f32,
+    /// Mean confidence of the segments.
+    pub mean: f32,
+    /// Confidence that a tenth of the segments are below.
+    pub p10: f32,
+    /// Median confidence of the segments.
+    pub p50: f32,
+    /// Confidence that nine tenths of the segments are below.
+    pub p90: f32,
 }
 
 /// A single utterance in the transcript.
 /// Contains a start and stop timestamp.
 /// Also contains the text of the utterance.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Utterance {
-    /// Timestamp of the start of the utterance, in ms
+    /// Timestamp of the start of the utterance, in centiseconds. Use [`to_millis`] to
+    /// convert it to milliseconds.
     pub start: i64,
-    /// Timestamp of the end of the utterance, in ms
+    /// Timestamp of the end of the utterance, in centiseconds. Use [`to_millis`] to
+    /// convert it to milliseconds.
     pub stop: i64,
-    /// Text of the utterance.
+    /// Text of the utterance, exactly as whisper produced it, usually with a leading
+    /// space. The output methods, such as [`Transcript::as_srt`], trim it; use
+    /// [`Transcript::as_raw_text`] or [`Transcript::as_raw_srt`] to output it verbatim.
     pub text: String,
+    /// Probability whisper assigned to this utterance, from 0 to 1.
+    /// Only present for words in [`Transcript::word_utterances`].
+    pub probability: Option<f32>,
+    /// Ratio of the length of the text to its length once compressed with zlib, as
+    /// used by the Python implementation. Repetitive hallucinations compress well, so
+    /// a ratio above about 2.4 is a strong sign of one. Only present for utterances in
+    /// [`Transcript::utterances`].
+    #[serde(default)]
+    pub compression_ratio: Option<f32>,
+    /// Average log probability of the tokens of the utterance, as in the Python
+    /// implementation. Closer to 0 is more confident; segments below -1 are usually
+    /// poor. Only present for utterances in [`Transcript::utterances`].
+    ///
+    /// The Python implementation also reports the temperature each segment was
+    /// decoded at, but whisper.cpp doesn't expose it. With a positive
+    /// [`TranscribeOptions::temperature_inc`](crate::TranscribeOptions::temperature_inc),
+    /// segments still below
+    /// [`TranscribeOptions::logprob_thold`](crate::TranscribeOptions::logprob_thold)
+    /// may have gone through every fallback temperature, though whisper.cpp skips the
+    /// fallback for windows it judges to have no speech.
+    #[serde(default)]
+    pub avg_logprob: Option<f32>,
+    /// Language the utterance was transcribed in, such as `"en"`. Only present when
+    /// the language was detected for every part of the audio separately, see
+    /// [`Model::transcribe_code_switched`].
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Start of the 30 second window on a fixed grid from the start of the audio that
+    /// the utterance starts in, in centiseconds. This only approximates `seek` in the
+    /// JSON of whisper.cpp and the Python implementation: whisper starts each window
+    /// where the last complete segment of the previous one ended, but whisper-rs 0.11
+    /// doesn't expose where that was, so the two differ once a window ends early. Only
+    /// present for utterances in [`Transcript::utterances`].
+    #[serde(default)]
+    pub seek: Option<i64>,
+    /// Label of the speaker of the utterance. Only present in transcripts merged from
+    /// separately transcribed channels, see [`Transcript::merge_channels`].
+    #[serde(default)]
+    pub speaker: Option<String>,
+}
+
+/// Line ending of subtitles. See [`Transcript::as_srt_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LineEnding {
+    /// `\n`, as on Unix.
+    #[default]
+    Lf,
+    /// `\r\n`, as on Windows, and as most hardware subtitle players expect.
+    CrLf,
+}
+
+impl LineEnding {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+}
+
+/// A cue with presentation timestamps instead of raw whisper timestamps. Returned by
+/// [`Transcript::as_pts_cues`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PtsCue {
+    /// Presentation timestamp of the start of the cue.
+    pub start: i64,
+    /// Presentation timestamp of the end of the cue.
+    pub stop: i64,
+    /// Text of the cue.
+    pub text: String,
+}
+
+/// A turn of speech: consecutive utterances with no long pause between them.
+/// Returned by [`Transcript::segment_by_pauses`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Turn {
+    /// Speaker label, alternating between `0` and `1` from one turn to the next.
+    /// This is a guess based on pauses only, not actual speaker recognition.
+    pub speaker: usize,
+    /// Timestamp of the start of the turn, in centiseconds.
+    pub start: i64,
+    /// Timestamp of the end of the turn, in centiseconds.
+    pub stop: i64,
+    /// Utterances in the turn.
+    pub utterances: Vec<Utterance>,
+}
+
+impl Turn {
+    /// Returns the text of the turn, with its utterances joined by spaces.
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.utterances
+            .iter()
+            .map(|utterance| utterance.text.trim())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
 }
 
 impl Transcript {
-    /// Returns the transcript as a string.
+    /// Whether the transcript contains no speech: either whisper returned no
+    /// utterances, or they are all blank or whisper's `[BLANK_AUDIO]` marker.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.utterances.iter().all(|utterance| {
+            let text = utterance.text.trim();
+            text.is_empty() || text == "[BLANK_AUDIO]"
+        })
+    }
+
+    /// Returns the transcript as a string. Use [`Transcript::is_empty`] rather than
+    /// checking whether this is empty to detect transcripts without speech.
     #[must_use]
     pub fn as_text(&self) -> String {
+        in_time_order(&self.utterances)
+            .into_iter()
+            .fold(String::new(), |transcript, fragment| {
+                transcript + format!("{}\n", fragment.text.trim()).as_str()
+            })
+    }
+
+    /// Returns the transcript as a dialogue: a line per turn, starting with its
+    /// speaker's label, such as `"Host: Welcome to the show."`. Consecutive utterances
+    /// of the same speaker are joined into one turn. Utterances without a speaker, see
+    /// [`Utterance::speaker`], are on lines of their own without a label.
+    #[must_use]
+    pub fn as_text_with_speakers(&self) -> String {
+        let mut text = String::new();
+        let mut previous_speaker = None;
+        for utterance in in_time_order(&self.utterances) {
+            let utterance_text = utterance.text.trim();
+            match &utterance.speaker {
+                Some(speaker) if previous_speaker == Some(speaker) => {
+                    text.pop();
+                    text.push(' ');
+                }
+                Some(speaker) => {
+                    text.push_str(speaker);
+                    text.push_str(": ");
+                }
+                None => {}
+            }
+            text.push_str(utterance_text);
+            text.push('\n');
+            previous_speaker = utterance.speaker.as_ref();
+        }
+        text
+    }
+
+    /// Returns the text of every utterance exactly as whisper produced it, each
+    /// followed by a newline. Unlike [`Transcript::as_text`], nothing is trimmed, so
+    /// the output matches the whisper.cpp CLI's `-otxt`.
+    #[must_use]
+    pub fn as_raw_text(&self) -> String {
         self.utterances
             .iter()
             .fold(String::new(), |transcript, fragment| {
-                transcript + format!("{}\n", fragment.text.trim()).as_str()
+                transcript + fragment.text.as_str() + "\n"
             })
     }
 
-    /// Returns the transcript in VTT format.
+    /// Returns the byte range of every utterance's text within [`Transcript::as_text`],
+    /// e.g. to map a selection in a displayed transcript back to a time. The ranges
+    /// exclude the newline after each utterance.
     #[must_use]
-    pub fn as_vtt(&self) -> String {
-        let vtt = self
-            .utterances
+    pub fn text_offsets(&self) -> Vec<(Range<usize>, &Utterance)> {
+        let mut offset = 0;
+        self.utterances
             .iter()
+            .map(|utterance| {
+                let start = offset;
+                offset += utterance.text.trim().len();
+                let range = start..offset;
+                // Skip the newline.
+                offset += 1;
+                (range, utterance)
+            })
+            .collect()
+    }
+
+    /// Returns the text of the transcript, with every utterance trimmed and joined
+    /// with `separator`, e.g. `" "` for a single line of text.
+    #[must_use]
+    pub fn join_with(&self, separator: &str) -> String {
+        self.utterances
+            .iter()
+            .map(|utterance| utterance.text.trim())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    /// Returns the transcript as one `[HH:MM:SS] text` line per utterance, with the
+    /// start time of the utterance wrapped in `open` and `close`, e.g. `"["` and
+    /// `"]"`. Handy for logs and chat messages, where SRT is too verbose.
+    #[must_use]
+    pub fn as_timecoded(&self, open: &str, close: &str) -> String {
+        in_time_order(&self.utterances)
+            .into_iter()
             .fold(String::new(), |transcript, fragment| {
+                let seconds = to_millis(fragment.start).max(0) / 1000;
                 transcript
                     + format!(
-                        "{} --> {}\n{}\n\n",
-                        format_timestamp(fragment.start, true, "."),
-                        format_timestamp(fragment.stop, true, "."),
-                        fragment.text.trim().replace("-->", "->")
+                        "{open}{:02}:{:02}:{:02}{close} {}\n",
+                        seconds / 3600,
+                        seconds / 60 % 60,
+                        seconds % 60,
+                        fragment.text.trim()
                     )
                     .as_str()
+            })
+    }
+
+    /// Parses SRT subtitles, such as those returned by [`Transcript::as_srt`], back
+    /// into a transcript. Only the utterances are restored; there are no words.
+    /// # Errors
+    /// - [`ModelError::SubtitleParseError`], if a cue's timing is invalid.
+    pub fn from_srt(srt: &str) -> Result<Self, ModelError> {
+        Ok(Self {
+            utterances: parse_cues(srt.trim_start_matches('\u{feff}'), 0)?,
+            ..Self::default()
+        })
+    }
+
+    /// Parses VTT subtitles, such as those returned by [`Transcript::as_vtt`], back
+    /// into a transcript. Only the utterances are restored; there are no words, and
+    /// cue settings are ignored.
+    /// # Errors
+    /// - [`ModelError::SubtitleParseError`], if the `WEBVTT` header is missing or a
+    ///   cue's timing is invalid.
+    pub fn from_vtt(vtt: &str) -> Result<Self, ModelError> {
+        let vtt = vtt.trim_start_matches('\u{feff}');
+        if !vtt.starts_with("WEBVTT") {
+            return Err(ModelError::SubtitleParseError(1));
+        }
+        Ok(Self {
+            utterances: parse_cues(vtt, 1)?,
+            ..Self::default()
+        })
+    }
+
+    /// Formats the transcript with any [`TranscriptFormatter`], such as the built-in
+    /// [`Srt`](crate::formatter::Srt) or your own format.
+    #[must_use]
+    pub fn format_with(&self, formatter: &impl TranscriptFormatter) -> String {
+        formatter.format(self)
+    }
+
+    /// Returns the transcript in VTT format.
+    #[must_use]
+    pub fn as_vtt(&self) -> String {
+        self.vtt(None)
+    }
+
+    /// Returns the transcript in VTT format like [`Transcript::as_vtt`], with every
+    /// time rounded to the nearest frame boundary at `fps` frames per second, e.g. 25
+    /// or 29.97, so cues line up with the frames of a video.
+    #[must_use]
+    pub fn as_vtt_at_fps(&self, fps: f64) -> String {
+        self.vtt(Some(fps))
+    }
+
+    /// Returns the transcript in VTT format, snapped to frames of `snap_to_fps` if
+    /// given.
+    fn vtt(&self, snap_to_fps: Option<f64>) -> String {
+        let vtt = in_time_order(&self.utterances)
+            .into_iter()
+            .fold(String::new(), |transcript, fragment| {
+                transcript + vtt_cue(fragment, snap_to_fps).as_str()
             });
         format!("WEBVTT\n{vtt}")
     }
 
-    /// Returns the transcript in SRT format.
+    /// Returns the transcript as an Audacity label track: one
+    /// `start\tend\ttext` line per utterance, with times in seconds. Import it in
+    /// Audacity with File > Import > Labels.
     #[must_use]
-    pub fn as_srt(&self) -> String {
+    pub fn as_audacity_labels(&self) -> String {
+        in_time_order(&self.utterances)
+            .into_iter()
+            .fold(String::new(), |labels, fragment| {
+                let text = fragment.text.trim().replace(['\t', '\n', '\r'], " ");
+                labels
+                    + format!(
+                        "{}\t{}\t{text}\n",
+                        format_seconds(fragment.start),
+                        format_seconds(fragment.stop)
+                    )
+                    .as_str()
+            })
+    }
+
+    /// Returns the utterances as cues timed in presentation timestamps with the given
+    /// `timescale`, for muxing into a container, e.g. 90000 for a 90 kHz clock. See
+    /// [`to_pts`].
+    #[must_use]
+    pub fn as_pts_cues(&self, timescale: u32) -> Vec<PtsCue> {
+        in_time_order(&self.utterances)
+            .into_iter()
+            .map(|utterance| PtsCue {
+                start: to_pts(utterance.start, timescale),
+                stop: to_pts(utterance.stop, timescale),
+                text: utterance.text.trim().to_owned(),
+            })
+            .collect()
+    }
+
+    /// Returns the words of the transcript as a JSON array of
+    /// `{"word": ..., "start_ms": ..., "end_ms": ...}` objects, for highlighting each
+    /// word as it is spoken.
+    /// # Errors
+    /// - [`ModelError::WordTimestampsUnavailable`], if `word_timestamps` wasn't `true`
+    ///   when transcribing.
+    /// - [`ModelError::SerializationError`], if the words couldn't be serialized.
+    pub fn as_word_timed_json(&self) -> Result<String, ModelError> {
+        #[derive(Serialize)]
+        struct TimedWord<'a> {
+            word: &'a str,
+            start_ms: i64,
+            end_ms: i64,
+        }
+
+        let words = self
+            .word_utterances
+            .as_ref()
+            .ok_or(ModelError::WordTimestampsUnavailable)?;
+        let words = in_time_order(words)
+            .into_iter()
+            .filter(|word| !word.text.trim().is_empty())
+            .map(|word| TimedWord {
+                word: word.text.trim(),
+                start_ms: to_millis(word.start),
+                end_ms: to_millis(word.stop),
+            })
+            .collect::<Vec<_>>();
+        serde_json::to_string(&words).map_err(ModelError::SerializationError)
+    }
+
+    /// Returns the transcript in SRT format with a cue for every word, for captions
+    /// that show one word at a time. Tokens are merged into whole words, with any
+    /// punctuation attached to the word before it.
+    /// # Errors
+    /// - [`ModelError::WordTimestampsUnavailable`], if `word_timestamps` wasn't `true`
+    ///   when transcribing.
+    pub fn as_word_srt(&self) -> Result<String, ModelError> {
+        let tokens = self
+            .word_utterances
+            .as_ref()
+            .ok_or(ModelError::WordTimestampsUnavailable)?;
+        Ok(merge_tokens_into_words(in_time_order(tokens))
+            .iter()
+            .filter(|word| !word.text.trim().is_empty())
+            .zip(1..)
+            .fold(String::new(), |transcript, (word, index)| {
+                transcript + srt_cue(index, word, None).as_str()
+            }))
+    }
+
+    /// Returns the transcript as JSON Lines: one
+    /// `{"start_ms": ..., "end_ms": ..., "text": ...}` object per utterance, each on its
+    /// own line. Convenient for line-oriented tools and log ingestion. Utterances with
+    /// an [`Utterance::seek`] also have a `"seek"`, in centiseconds.
+    /// # Errors
+    /// - [`ModelError::SerializationError`], if an utterance couldn't be serialized.
+    pub fn as_jsonl(&self) -> Result<String, ModelError> {
+        in_time_order(&self.utterances)
+            .into_iter()
+            .try_fold(String::new(), |mut jsonl, utterance| {
+                jsonl.push_str(&jsonl_line(utterance)?);
+                Ok(jsonl)
+            })
+            .map_err(ModelError::SerializationError)
+    }
+
+    /// Total duration of speech in the transcript, i.e. the sum of the durations of
+    /// all utterances.
+    #[must_use]
+    pub fn total_speech_duration(&self) -> Duration {
         self.utterances
             .iter()
+            .map(|utterance| raw_to_duration(utterance.stop - utterance.start))
+            .sum()
+    }
+
+    /// Fraction of the audio, from 0 to 1, that isn't speech. `None` if the duration of
+    /// the audio isn't known.
+    #[must_use]
+    pub fn silence_ratio(&self) -> Option<f32> {
+        let audio_duration = self.audio_duration.filter(|duration| !duration.is_zero())?;
+        let speech = self.total_speech_duration().as_secs_f32() / audio_duration.as_secs_f32();
+        Some((1.0 - speech).clamp(0.0, 1.0))
+    }
+
+    /// Lists the gaps between utterances that are at least `min_gap` long, including
+    /// before the first utterance and, if the duration of the audio is known, after
+    /// the last one.
+    #[must_use]
+    pub fn silence_gaps(&self, min_gap: Duration) -> Vec<Range<Duration>> {
+        let mut gaps = Vec::new();
+        let mut speech_end = Duration::ZERO;
+        for utterance in &self.utterances {
+            let start = raw_to_duration(utterance.start);
+            if start >= speech_end + min_gap {
+                gaps.push(speech_end..start);
+            }
+            speech_end = speech_end.max(raw_to_duration(utterance.stop));
+        }
+        if let Some(audio_duration) = self.audio_duration {
+            if audio_duration >= speech_end + min_gap && audio_duration > speech_end {
+                gaps.push(speech_end..audio_duration);
+            }
+        }
+        gaps
+    }
+
+    /// Groups utterances into turns, starting a new turn whenever the pause between two
+    /// utterances is at least `min_pause`, and alternates the speaker label between
+    /// turns. This is not diarization, but gives dialogue a useful structure.
+    #[must_use]
+    pub fn segment_by_pauses(&self, min_pause: Duration) -> Vec<Turn> {
+        let min_pause = duration_to_raw(min_pause);
+        let mut turns: Vec<Turn> = Vec::new();
+        for utterance in &self.utterances {
+            match turns.last_mut() {
+                Some(turn) if utterance.start - turn.stop < min_pause => {
+                    turn.stop = turn.stop.max(utterance.stop);
+                    turn.utterances.push(utterance.clone());
+                }
+                _ => turns.push(Turn {
+                    speaker: turns.len() % 2,
+                    start: utterance.start,
+                    stop: utterance.stop,
+                    utterances: vec![utterance.clone()],
+                }),
+            }
+        }
+        turns
+    }
+
+    /// Splits the transcript into chapters, e.g. for video chapter markers, guessing
+    /// that topics change at pauses of at least `min_gap`. A chapter only ends at such a
+    /// pause once it's at least `min_chapter_len` long, so a few pauses in quick
+    /// succession don't give a run of tiny chapters. Each chapter comes with
+    /// its start time and its first sentence as a title; the first chapter starts at
+    /// zero, as video sites expect.
+    #[must_use]
+    pub fn chapters(
+        &self,
+        min_gap: Duration,
+        min_chapter_len: Duration,
+    ) -> Vec<(Duration, String)> {
+        let min_chapter_len = duration_to_raw(min_chapter_len);
+        let mut chapters: Vec<(i64, String)> = Vec::new();
+        for turn in self.segment_by_pauses(min_gap) {
+            match chapters.last_mut() {
+                Some((start, title)) if turn.start - *start < min_chapter_len => {
+                    if title.is_empty() {
+                        first_sentence(&turn.text()).clone_into(title);
+                    }
+                }
+                _ => chapters.push((turn.start, first_sentence(&turn.text()).to_owned())),
+            }
+        }
+        chapters
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (start, title))| {
+                let start = if idx == 0 {
+                    Duration::ZERO
+                } else {
+                    raw_to_duration(start)
+                };
+                (start, title)
+            })
+            .collect()
+    }
+
+    /// Groups [`Transcript::word_utterances`] into consecutive buckets of `bucket`
+    /// length, e.g. to scroll a transcript along with a waveform. Each bucket comes with
+    /// its start time. A word that spans the edge between buckets is in every bucket it
+    /// overlaps, so it's shown for as long as it's spoken. Buckets without words are
+    /// kept, so the `n`th bucket always starts at `n * bucket`, and they run until the
+    /// end of the audio if its duration is known, or else the last word.
+    /// # Errors
+    /// - [`ModelError::WordTimestampsUnavailable`], if `word_timestamps` wasn't `true`
+    ///   when transcribing.
+    /// # Panics
+    /// If `bucket` is zero.
+    pub fn words_in_buckets(
+        &self,
+        bucket: Duration,
+    ) -> Result<Vec<(Duration, Vec<&Utterance>)>, ModelError> {
+        assert!(!bucket.is_zero(), "buckets must not be empty");
+        let words = self
+            .word_utterances
+            .as_ref()
+            .ok_or(ModelError::WordTimestampsUnavailable)?;
+        let index = |time: Duration| {
+            usize::try_from(time.as_nanos() / bucket.as_nanos()).unwrap_or(usize::MAX)
+        };
+        // Buckets are half-open, so something ending right at the edge of a bucket
+        // isn't in the next one.
+        let last_index = |start: Duration, end: Duration| {
+            index(end.saturating_sub(Duration::from_nanos(1)).max(start))
+        };
+        let grow = |buckets: &mut Vec<(Duration, Vec<&Utterance>)>, last: usize| {
+            while buckets.len() <= last {
+                let start = bucket * u32::try_from(buckets.len()).unwrap_or(u32::MAX);
+                buckets.push((start, Vec::new()));
+            }
+        };
+
+        let mut buckets = Vec::new();
+        for word in words.iter().filter(|word| !word.text.starts_with("[_")) {
+            let start = raw_to_duration(word.start);
+            let last = last_index(start, raw_to_duration(word.stop));
+            grow(&mut buckets, last);
+            for (_, bucket_words) in &mut buckets[index(start)..=last] {
+                bucket_words.push(word);
+            }
+        }
+        if let Some(audio_duration) = self.audio_duration.filter(|d| !d.is_zero()) {
+            grow(&mut buckets, last_index(Duration::ZERO, audio_duration));
+        }
+        Ok(buckets)
+    }
+
+    /// Aggregates the confidence of the segments in [`Transcript::utterances`], see
+    /// [`ConfidenceStats`], e.g. to flag poor transcriptions for review. Percentiles
+    /// use the nearest rank.
+    /// # Returns
+    /// [`ConfidenceStats`]. `None` if no segment has an [`Utterance::avg_logprob`],
+    /// e.g. because the transcript is empty or was parsed from subtitles.
+    #[must_use]
+    pub fn confidence_stats(&self) -> Option<ConfidenceStats> {
+        let mut confidences: Vec<f32> = self
+            .utterances
+            .iter()
+            .filter_map(|utterance| utterance.avg_logprob)
+            .map(f32::exp)
+            .collect();
+        confidences.sort_by(f32::total_cmp);
+        let (&min, &max) = (confidences.first()?, confidences.last()?);
+        let percentile = |percent: usize| {
+            let rank = (percent * confidences.len()).div_ceil(100).max(1);
+            confidences[rank - 1]
+        };
+        #[allow(clippy::cast_precision_loss)]
+        let mean = confidences.iter().sum::<f32>() / confidences.len() as f32;
+        Some(ConfidenceStats {
+            segments: confidences.len(),
+            min,
+            max,
+            mean,
+            p10: percentile(10),
+            p50: percentile(50),
+            p90: percentile(90),
+        })
+    }
+
+    /// Returns the part of the transcript that overlaps `range`, e.g. to clip a
+    /// highlight out of a long recording. Utterances that overlap the range at all are
+    /// kept whole, along with their words in [`Transcript::word_utterances`], so no
+    /// utterance loses words and no word is left without its utterance.
+    ///
+    /// # Arguments
+    /// - `range`: Time range to keep, from the start of the audio.
+    /// - `rebase`: Whether to shift timestamps so the slice starts at 0, as if it were
+    ///   a recording of its own. Utterances that started before the range then start
+    ///   at 0, and the audio duration becomes that of the range.
+    /// # Returns
+    /// [Transcript] of the range, without timings.
+    #[must_use]
+    pub fn slice(&self, range: Range<Duration>, rebase: bool) -> Self {
+        let (start, end) = (duration_to_raw(range.start), duration_to_raw(range.end));
+        let overlaps = |utterance: &Utterance| {
+            utterance.start < end && (utterance.stop > start || utterance.start >= start)
+        };
+        let utterances: Vec<Utterance> = self
+            .utterances
+            .iter()
+            .filter(|utterance| overlaps(utterance))
+            .cloned()
+            .collect();
+        let word_utterances = self.word_utterances.as_ref().map(|words| {
+            words
+                .iter()
+                .filter(|word| {
+                    utterances.iter().any(|utterance| {
+                        word.start == utterance.start
+                            || (utterance.start..utterance.stop).contains(&word.start)
+                    })
+                })
+                .cloned()
+                .collect()
+        });
+        let mut slice = Self {
+            utterances,
+            word_utterances,
+            audio_duration: self.audio_duration,
+            clipping_ratio: self.clipping_ratio,
+            source_format: self.source_format,
+            speech_ranges: self.speech_ranges.as_ref().map(|ranges| {
+                ranges
+                    .iter()
+                    .filter(|&&(start, end)| start < range.end && end > range.start)
+                    .map(|&(start, end)| (start.max(range.start), end.min(range.end)))
+                    .collect()
+            }),
+            ..Self::default()
+        };
+
+        if rebase {
+            slice.offset_by(-start);
+            let words = slice.word_utterances.iter_mut().flatten();
+            for utterance in slice.utterances.iter_mut().chain(words) {
+                utterance.start = utterance.start.max(0);
+                utterance.stop = utterance.stop.max(0);
+            }
+            let audio_end = self
+                .audio_duration
+                .map_or(range.end, |duration| duration.min(range.end));
+            slice.audio_duration = Some(audio_end.saturating_sub(range.start));
+        }
+        slice
+    }
+
+    /// Removes utterances whose [`Utterance::compression_ratio`] is above
+    /// `max_compression_ratio`, which are most likely hallucinated, along with their
+    /// words. The Python implementation uses a threshold of 2.4.
+    pub fn remove_hallucinations(&mut self, max_compression_ratio: f32) {
+        let (kept, removed): (Vec<_>, Vec<_>) = self.utterances.drain(..).partition(|utterance| {
+            utterance
+                .compression_ratio
+                .is_none_or(|ratio| ratio <= max_compression_ratio)
+        });
+        self.utterances = kept;
+        if let Some(words) = &mut self.word_utterances {
+            words.retain(|word| {
+                !removed
+                    .iter()
+                    .any(|utterance| (utterance.start..utterance.stop).contains(&word.start))
+            });
+        }
+    }
+
+    /// Returns the transcript as HTML, with every word wrapped in a `<span>` whose
+    /// class reflects how confident whisper was in it: `mutter-confidence-high`,
+    /// `mutter-confidence-medium` or `mutter-confidence-low`. The probability is also
+    /// applied as the span's opacity, so it's readable without any stylesheet.
+    ///
+    /// Word confidence is only available if `word_timestamps` was `true` when
+    /// transcribing. Otherwise, whole segments are wrapped in spans with the class
+    /// `mutter-confidence-unknown`.
+    #[must_use]
+    pub fn as_html(&self) -> String {
+        let fragments = self.word_utterances.as_ref().unwrap_or(&self.utterances);
+        let spans = in_time_order(fragments)
+            .into_iter()
+            .filter(|fragment| !fragment.text.trim().is_empty())
+            .map(|fragment| {
+                let text = escape_html(fragment.text.trim());
+                match fragment.probability {
+                    Some(probability) => {
+                        let class = if probability >= 0.8 {
+                            "high"
+                        } else if probability >= 0.5 {
+                            "medium"
+                        } else {
+                            "low"
+                        };
+                        format!(
+                            "<span class=\"mutter-confidence-{class}\" style=\"opacity: {:.2}\" title=\"{:.0}%\">{text}</span>",
+                            probability.clamp(0.25, 1.0),
+                            probability * 100.0
+                        )
+                    }
+                    None => format!("<span class=\"mutter-confidence-unknown\">{text}</span>"),
+                }
+            })
+            .collect::<Vec<_>>();
+        format!("<p class=\"mutter-transcript\">{}</p>\n", spans.join(" "))
+    }
+
+    /// Replaces whole words or phrases in the text of every utterance and word, e.g. to
+    /// fix domain terms whisper consistently gets wrong. Keys of `replacements` are
+    /// matched case-insensitively, and only at word boundaries, so `"cat"` doesn't
+    /// match `"catalog"`. If the matched text is capitalized, so is the replacement.
+    /// Timestamps are left untouched.
+    pub fn apply_replacements(&mut self, replacements: &HashMap<String, String>) {
+        let mut replacements = replacements
+            .iter()
+            .filter(|(from, _)| !from.is_empty())
+            .map(|(from, to)| (from.to_lowercase(), to.as_str()))
+            .collect::<Vec<_>>();
+        // Try longer phrases first, so they win over words they contain.
+        replacements.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        let words = self.word_utterances.iter_mut().flatten();
+        for utterance in self.utterances.iter_mut().chain(words) {
+            utterance.text = replace_whole_words(&utterance.text, &replacements);
+        }
+    }
+
+    /// Merges transcripts of the same audio, e.g. from different models, keeping the
+    /// most confident utterance wherever they overlap in time, i.e. by at least half
+    /// of the shorter utterance. Useful to ensemble
+    /// models on critical audio:
+    ///
+    /// ```no_run
+    /// use mutter::{transcript::Transcript, Model, ModelType, TranscribeOptions};
+    ///
+    /// let audio = std::fs::read("interview.mp3").unwrap();
+    /// let options = TranscribeOptions {
+    ///     word_timestamps: true,
+    ///     ..TranscribeOptions::default()
+    /// };
+    /// let transcripts = [ModelType::BaseEn, ModelType::MediumEn]
+    ///     .iter()
+    ///     .map(|model_type| Model::download(model_type)?.transcribe(&audio, &options))
+    ///     .collect::<Result<Vec<_>, _>>()
+    ///     .unwrap();
+    /// let merged = Transcript::merge_by_confidence(transcripts);
+    /// ```
+    ///
+    /// The confidence of an utterance is the mean probability of its words, so
+    /// transcribe with `word_timestamps` set; otherwise, utterances from earlier
+    /// transcripts win. The merged utterances have their confidence as
+    /// [`Utterance::probability`]. Processing times are summed.
+    #[must_use]
+    pub fn merge_by_confidence(transcripts: Vec<Self>) -> Self {
+        let mut merged = Self {
+            audio_duration: transcripts
+                .iter()
+                .filter_map(|transcript| transcript.audio_duration)
+                .max(),
+            word_utterances: transcripts
+                .iter()
+                .any(|transcript| transcript.word_utterances.is_some())
+                .then(Vec::new),
+            ..Self::default()
+        };
+
+        let mut candidates = Vec::new();
+        for (rank, transcript) in transcripts.into_iter().enumerate() {
+            merged.processing_time += transcript.processing_time;
+            merged.timings.decode += transcript.timings.decode;
+            merged.timings.inference += transcript.timings.inference;
+            merged.timings.extraction += transcript.timings.extraction;
+            let words = transcript.word_utterances.unwrap_or_default();
+            for mut utterance in transcript.utterances {
+                let utterance_words = words
+                    .iter()
+                    .filter(|word| (utterance.start..utterance.stop).contains(&word.start))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let probabilities = utterance_words
+                    .iter()
+                    .filter_map(|word| word.probability)
+                    .collect::<Vec<_>>();
+                if !probabilities.is_empty() {
+                    #[allow(clippy::cast_precision_loss)]
+                    let mean = probabilities.iter().sum::<f32>() / probabilities.len() as f32;
+                    utterance.probability = Some(mean);
+                }
+                candidates.push((rank, utterance, utterance_words));
+            }
+        }
+
+        // Most confident first, then earliest transcript first.
+        candidates.sort_by(|(rank_a, a, _), (rank_b, b, _)| {
+            let confidence_a = a.probability.unwrap_or(f32::NEG_INFINITY);
+            let confidence_b = b.probability.unwrap_or(f32::NEG_INFINITY);
+            confidence_b
+                .total_cmp(&confidence_a)
+                .then(rank_a.cmp(rank_b))
+        });
+        for (_, utterance, words) in candidates {
+            // Models rarely agree on exact boundaries, so only utterances overlapping by
+            // more than half of the shorter one are considered the same.
+            let overlaps = merged.utterances.iter().any(|kept| {
+                let overlap = utterance.stop.min(kept.stop) - utterance.start.max(kept.start);
+                let shortest = (utterance.stop - utterance.start).min(kept.stop - kept.start);
+                overlap > 0 && overlap * 2 >= shortest
+            });
+            if !overlaps {
+                merged.utterances.push(utterance);
+                if let Some(merged_words) = &mut merged.word_utterances {
+                    merged_words.extend(words);
+                }
+            }
+        }
+        merged.utterances.sort_by_key(|utterance| utterance.start);
+        if let Some(words) = &mut merged.word_utterances {
+            words.sort_by_key(|word| word.start);
+        }
+        merged
+    }
+
+    /// Merges transcripts of the channels of one recording, transcribed separately,
+    /// into one transcript ordered by time, e.g. for an interview with a microphone per
+    /// speaker. Every utterance and word is labelled with its channel's speaker, see
+    /// [`Utterance::speaker`], and [`Transcript::as_text_with_speakers`] turns the
+    /// result into a readable dialogue.
+    ///
+    /// Utterances that start at the same time stay in channel order. The audio
+    /// duration is that of the longest channel, and processing times are summed.
+    /// # Arguments
+    /// - `channel_transcripts`: A transcript per channel.
+    /// - `labels`: A speaker label per channel, such as `"Host"`. Channels without a
+    ///   label are labelled by their number, starting at `"Channel 1"`.
+    #[must_use]
+    pub fn merge_channels(channel_transcripts: Vec<Self>, labels: &[&str]) -> Self {
+        let audio_duration = channel_transcripts
+            .iter()
+            .filter_map(|transcript| transcript.audio_duration)
+            .max();
+        let mut merged = Self::default();
+        for (channel, mut transcript) in channel_transcripts.into_iter().enumerate() {
+            let label = labels.get(channel).map_or_else(
+                || format!("Channel {}", channel + 1),
+                |&label| label.to_owned(),
+            );
+            let words = transcript.word_utterances.iter_mut().flatten();
+            for utterance in transcript.utterances.iter_mut().chain(words) {
+                utterance.speaker = Some(label.clone());
+            }
+            merged.append(transcript);
+        }
+        merged.audio_duration = audio_duration;
+        merged.sort_by_time();
+        merged
+    }
+
+    /// Appends another transcript to the end of this one. Timestamps are kept as-is.
+    pub(crate) fn append(&mut self, other: Self) {
+        self.processing_time += other.processing_time;
+        self.audio_duration = self
+            .audio_duration
+            .zip(other.audio_duration)
+            .map(|(duration, other_duration)| duration + other_duration);
+        self.timings.decode += other.timings.decode;
+        self.timings.inference += other.timings.inference;
+        self.timings.extraction += other.timings.extraction;
+        self.utterances.extend(other.utterances);
+        if let Some(other_words) = other.word_utterances {
+            self.word_utterances
+                .get_or_insert_with(Vec::new)
+                .extend(other_words);
+        }
+        if let Some(other_ranges) = other.speech_ranges {
+            self.speech_ranges
+                .get_or_insert_with(Vec::new)
+                .extend(other_ranges);
+        }
+    }
+
+    /// Shifts every timestamp in the transcript by `offset`, in the same units as
+    /// [`Utterance::start`].
+    pub(crate) fn offset_by(&mut self, offset: i64) {
+        let words = self.word_utterances.iter_mut().flatten();
+        for utterance in self.utterances.iter_mut().chain(words) {
+            utterance.start += offset;
+            utterance.stop += offset;
+            if let Some(seek) = &mut utterance.seek {
+                // Stay on the grid of the whole audio rather than of the part.
+                *seek = window_start(utterance.start);
+            }
+        }
+        let shift = raw_to_duration(offset.abs());
+        for (start, end) in self.speech_ranges.iter_mut().flatten() {
+            for time in [start, end] {
+                *time = if offset < 0 {
+                    time.saturating_sub(shift)
+                } else {
+                    *time + shift
+                };
+            }
+        }
+    }
+
+    /// Returns the transcript in SRT format, with the text of every utterance exactly
+    /// as whisper produced it and a blank line after every cue. Unlike
+    /// [`Transcript::as_srt`], nothing is trimmed and `-->` isn't escaped, so the
+    /// output matches the whisper.cpp CLI's `-osrt`.
+    #[must_use]
+    pub fn as_raw_srt(&self) -> String {
+        in_time_order(&self.utterances).into_iter().zip(1..).fold(
+            String::new(),
+            |transcript, (fragment, index)| {
+                transcript
+                    + format!(
+                        "{index}\n{} --> {}\n{}\n\n",
+                        format_timestamp(fragment.start, true, ","),
+                        format_timestamp(fragment.stop, true, ","),
+                        fragment.text
+                    )
+                    .as_str()
+            },
+        )
+    }
+
+    /// Sorts the utterances and words by start, then stop, keeping the order of those
+    /// with the same times, e.g. after pushing utterances from several transcripts
+    /// into one.
+    pub fn sort_by_time(&mut self) {
+        let words = self.word_utterances.iter_mut();
+        for utterances in [&mut self.utterances].into_iter().chain(words) {
+            utterances.sort_by_key(|utterance| (utterance.start, utterance.stop));
+        }
+    }
+
+    /// Returns the transcript in SRT format for strict players, such as hardware
+    /// set-top boxes: every cue is followed by a blank line, as the SRT format
+    /// requires, lines end with `line_ending`, and the output optionally starts with a
+    /// UTF-8 byte order mark. Text is trimmed and escaped as in [`Transcript::as_srt`].
+    ///
+    /// # Arguments
+    /// - `line_ending`: [`LineEnding`] to end every line with. Many hardware players
+    ///   only accept [`LineEnding::CrLf`].
+    /// - `bom`: Whether to start with a byte order mark. Some players need one to
+    ///   detect UTF-8, while others display it as a stray character.
+    #[must_use]
+    pub fn as_srt_with(&self, line_ending: LineEnding, bom: bool) -> String {
+        let mut srt = String::from(if bom { "\u{feff}" } else { "" });
+        for (utterance, index) in in_time_order(&self.utterances).into_iter().zip(1..) {
+            for line in srt_cue(index, utterance, None).lines().chain([""]) {
+                srt.push_str(line);
+                srt.push_str(line_ending.as_str());
+            }
+        }
+        srt
+    }
+
+    /// Returns the transcript in SRT format.
+    #[must_use]
+    pub fn as_srt(&self) -> String {
+        self.srt(None)
+    }
+
+    /// Returns the transcript in SRT format like [`Transcript::as_srt`], with every
+    /// time rounded to the nearest frame boundary at `fps` frames per second, e.g. 25
+    /// or 29.97, so cues line up with the frames of a video.
+    #[must_use]
+    pub fn as_srt_at_fps(&self, fps: f64) -> String {
+        self.srt(Some(fps))
+    }
+
+    /// Returns the transcript in SRT format, snapped to frames of `snap_to_fps` if
+    /// given.
+    fn srt(&self, snap_to_fps: Option<f64>) -> String {
+        in_time_order(&self.utterances)
+            .into_iter()
             .fold((1, String::new()), |(i, transcript), fragment| {
                 (
                     i + 1,
-                    transcript
-                        + format!(
-                            "{i}\n{} --> {}\n{}\n",
-                            format_timestamp(fragment.start, true, ","),
-                            format_timestamp(fragment.stop, true, ","),
-                            fragment.text.trim().replace("-->", "->")
-                        )
-                        .as_str(),
+                    transcript + srt_cue(i, fragment, snap_to_fps).as_str(),
                 )
             })
             .1
     }
+
+    /// Writes the transcript in every standard format to `dir`, like the whisper.cpp
+    /// CLI does with `-otxt -osrt -ovtt -oj`: `{base_name}.txt` with
+    /// [`Transcript::as_text`], `{base_name}.srt` with [`Transcript::as_srt`],
+    /// `{base_name}.vtt` with [`Transcript::as_vtt`] and `{base_name}.json` with the
+    /// whole transcript serialized. `dir` is created if it doesn't exist, and existing
+    /// files are overwritten.
+    /// # Errors
+    /// - [`std::io::Error`], if `dir` or a file can't be written, or the transcript
+    ///   can't be serialized.
+    pub fn write_all(&self, dir: impl AsRef<Path>, base_name: &str) -> io::Result<()> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(format!("{base_name}.txt")), self.as_text())?;
+        fs::write(dir.join(format!("{base_name}.srt")), self.as_srt())?;
+        fs::write(dir.join(format!("{base_name}.vtt")), self.as_vtt())?;
+        fs::write(
+            dir.join(format!("{base_name}.json")),
+            serde_json::to_vec(self)?,
+        )
+    }
+}
+
+/// Writes SRT cues one at a time, numbering them as it goes. Useful to write subtitles
+/// while a transcription is still running, see [`Model::transcribe_pcm_to_srt`].
+pub struct SrtWriter<W: Write> {
+    writer: W,
+    index: usize,
+}
+
+impl<W: Write> SrtWriter<W> {
+    /// Creates a new writer. The first cue written will be numbered 1.
+    pub const fn new(writer: W) -> Self {
+        Self { writer, index: 1 }
+    }
+
+    /// Writes the utterance as the next cue, and flushes the underlying writer.
+    /// # Errors
+    /// - [`std::io::Error`]
+    pub fn write_utterance(&mut self, utterance: &Utterance) -> io::Result<()> {
+        self.writer
+            .write_all(srt_cue(self.index, utterance, None).as_bytes())?;
+        self.index += 1;
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Writes utterances as JSON Lines one at a time, in the format of
+/// [`Transcript::as_jsonl`]. Useful to stream segments to a live transcript feed while
+/// a transcription is still running, see [`Model::transcribe_pcm_to_jsonl`].
+pub struct JsonlWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    /// Creates a new writer.
+    pub const fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Writes the utterance as the next line, and flushes the underlying writer so the
+    /// line reaches e.g. a socket right away.
+    /// # Errors
+    /// - [`std::io::Error`], including if the utterance couldn't be serialized.
+    pub fn write_utterance(&mut self, utterance: &Utterance) -> io::Result<()> {
+        self.writer.write_all(jsonl_line(utterance)?.as_bytes())?;
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Formats a single line of JSON Lines, including the trailing newline.
+fn jsonl_line(utterance: &Utterance) -> Result<String, serde_json::Error> {
+    #[derive(Serialize)]
+    struct Line<'a> {
+        start_ms: i64,
+        end_ms: i64,
+        text: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        seek: Option<i64>,
+    }
+
+    let line = Line {
+        start_ms: to_millis(utterance.start),
+        end_ms: to_millis(utterance.stop),
+        text: utterance.text.trim(),
+        seek: utterance.seek,
+    };
+    Ok(serde_json::to_string(&line)? + "\n")
+}
+
+/// Formats a single SRT cue, with its times snapped to frames of `snap_to_fps` if
+/// given.
+fn srt_cue(index: usize, fragment: &Utterance, snap_to_fps: Option<f64>) -> String {
+    format!(
+        "{index}\n{} --> {}\n{}\n",
+        format_millis(snap_millis(fragment.start, snap_to_fps), true, ","),
+        format_millis(snap_millis(fragment.stop, snap_to_fps), true, ","),
+        fragment.text.trim().replace("-->", "->")
+    )
+}
+
+/// Formats a single VTT cue, with its times snapped to frames of `snap_to_fps` if
+/// given.
+fn vtt_cue(fragment: &Utterance, snap_to_fps: Option<f64>) -> String {
+    format!(
+        "{} --> {}\n{}\n\n",
+        format_millis(snap_millis(fragment.start, snap_to_fps), true, "."),
+        format_millis(snap_millis(fragment.stop, snap_to_fps), true, "."),
+        fragment.text.trim().replace("-->", "->")
+    )
+}
+
+/// Converts a raw whisper timestamp to milliseconds, rounded to the nearest frame
+/// boundary at `snap_to_fps` frames per second if given and positive.
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn snap_millis(raw: i64, snap_to_fps: Option<f64>) -> i64 {
+    let millis = to_millis(raw);
+    match snap_to_fps {
+        Some(fps) if fps > 0.0 && fps.is_finite() => {
+            let frame = (millis as f64 * fps / 1000.0).round();
+            (frame * 1000.0 / fps).round() as i64
+        }
+        _ => millis,
+    }
+}
+
+/// Parses the cues of SRT or VTT subtitles, skipping the first `header_lines`. The
+/// text of a cue runs from its timing line up to a blank line, or up to the
+/// identifier of the next cue, as [`Transcript::as_srt`] doesn't separate cues.
+fn parse_cues(subtitles: &str, header_lines: usize) -> Result<Vec<Utterance>, ModelError> {
+    let lines = subtitles.lines().collect::<Vec<_>>();
+    let timing_lines = (header_lines..lines.len())
+        .filter(|&idx| lines[idx].contains("-->"))
+        .collect::<Vec<_>>();
+    let mut utterances = Vec::with_capacity(timing_lines.len());
+    for (cue, &timing_line) in timing_lines.iter().enumerate() {
+        let invalid = || ModelError::SubtitleParseError(timing_line + 1);
+        let (start, stop) = lines[timing_line].split_once("-->").ok_or_else(invalid)?;
+        let start = parse_timestamp(start.trim()).ok_or_else(invalid)?;
+        let stop = stop
+            .split_whitespace()
+            .next()
+            .and_then(parse_timestamp)
+            .ok_or_else(invalid)?;
+
+        // The line before the next timing line is that cue's identifier.
+        let text_end = timing_lines
+            .get(cue + 1)
+            .map_or(lines.len(), |next| next - 1);
+        let text = lines[timing_line + 1..text_end.max(timing_line + 1)]
+            .iter()
+            .take_while(|line| !line.trim().is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join("\n");
+        utterances.push(Utterance {
+            start,
+            stop,
+            text,
+            ..Utterance::default()
+        });
+    }
+    Ok(utterances)
+}
+
+/// Parses an SRT or VTT timestamp, such as `01:02:03,456` or `02:03.456`, into a raw
+/// whisper timestamp.
+fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let (clock, millis) = timestamp.split_once([',', '.'])?;
+    let millis = millis.parse::<u16>().ok().filter(|millis| *millis < 1000)?;
+    let mut parts = clock.rsplit(':').map(str::parse::<u32>);
+    let seconds = parts.next()?.ok().filter(|seconds| *seconds < 60)?;
+    let minutes = parts.next()?.ok().filter(|minutes| *minutes < 60)?;
+    let hours = parts.next().unwrap_or(Ok(0)).ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let millis = ((i64::from(hours) * 60 + i64::from(minutes)) * 60 + i64::from(seconds)) * 1000
+        + i64::from(millis);
+    Some(millis / WHISPER_TIMESTAMP_SCALE)
 }
 
 /// Timestamp is oddly given in number of seconds * 100, or number of milliseconds / 10.
-/// This function corrects it and formats it in the desired format.
+/// This function corrects it (see [`WHISPER_TIMESTAMP_SCALE`]) and formats it in the
+/// desired format.
 fn format_timestamp(num: i64, always_include_hours: bool, decimal_marker: &str) -> String {
-    assert!(num >= 0, "non-negative timestamp expected");
-    let mut milliseconds: i64 = num * 10;
+    format_millis(to_millis(num), always_include_hours, decimal_marker)
+}
+
+/// Formats a timestamp in milliseconds like [`format_timestamp`].
+fn format_millis(
+    mut milliseconds: i64,
+    always_include_hours: bool,
+    decimal_marker: &str,
+) -> String {
+    assert!(milliseconds >= 0, "non-negative timestamp expected");
 
     let hours = div_floor(milliseconds, 3_600_000);
     milliseconds -= hours * 3_600_000;
@@ -107,6 +1498,70 @@ fn format_timestamp(num: i64, always_include_hours: bool, decimal_marker: &str)
     format!("{hours_marker}{minutes:02}:{seconds:02}{decimal_marker}{milliseconds:03}")
 }
 
+/// Replaces every whole-word, case-insensitive match of the lowercase keys of
+/// `replacements` in `text`. `replacements` must be sorted by priority.
+fn replace_whole_words(text: &str, replacements: &[(String, &str)]) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut after_word = false;
+    while let Some(c) = rest.chars().next() {
+        if !after_word && c.is_alphanumeric() {
+            let found = replacements.iter().find_map(|(from, to)| {
+                let candidate = rest.get(..from.len())?;
+                let at_boundary = !rest[from.len()..].starts_with(char::is_alphanumeric);
+                (at_boundary && candidate.to_lowercase() == *from).then_some((candidate, *to))
+            });
+            if let Some((candidate, to)) = found {
+                if candidate.starts_with(char::is_uppercase) {
+                    let mut chars = to.chars();
+                    replaced.extend(chars.next().into_iter().flat_map(char::to_uppercase));
+                    replaced.push_str(chars.as_str());
+                } else {
+                    replaced.push_str(to);
+                }
+                rest = &rest[candidate.len()..];
+                after_word = true;
+                continue;
+            }
+        }
+        replaced.push(c);
+        after_word = c.is_alphanumeric();
+        rest = &rest[c.len_utf8()..];
+    }
+    replaced
+}
+
+/// Text up to and including the first sentence-ending punctuation mark followed by
+/// whitespace or the end of the text, or all of it if there is none.
+fn first_sentence(text: &str) -> &str {
+    let text = text.trim();
+    text.char_indices()
+        .find(|&(idx, c)| {
+            matches!(c, '.' | '!' | '?')
+                && text[idx + 1..]
+                    .chars()
+                    .next()
+                    .is_none_or(char::is_whitespace)
+        })
+        .map_or(text, |(idx, _)| &text[..=idx])
+}
+
+/// Formats a raw whisper timestamp as seconds with six decimals, e.g. `1.500000`.
+fn format_seconds(raw: i64) -> String {
+    let millis = to_millis(raw);
+    let sign = if millis < 0 { "-" } else { "" };
+    let millis = millis.unsigned_abs();
+    format!("{sign}{}.{:03}000", millis / 1000, millis % 1000)
+}
+
+/// Escapes the characters that have special meaning in HTML.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 #[test]
 fn test_format_timestamp() {
     let result = format_timestamp(100, true, ".");
@@ -124,3 +1579,806 @@ fn test_format_timestamp_seconds() {
     let result = format_timestamp(100, false, ".");
     assert_eq!(result, "00:01.000");
 }
+
+#[test]
+fn test_to_millis() {
+    assert_eq!(to_millis(123), 1230);
+    assert_eq!(to_millis(0), 0);
+}
+
+#[cfg(test)]
+fn utterance(start: i64, stop: i64, text: &str) -> Utterance {
+    Utterance {
+        start,
+        stop,
+        text: text.to_string(),
+        ..Utterance::default()
+    }
+}
+
+#[test]
+fn test_to_pts() {
+    assert_eq!(to_pts(150, 90_000), 135_000);
+    assert_eq!(to_pts(1, 1000), 10);
+    assert_eq!(to_pts(1, 48_000), 480);
+    assert_eq!(to_pts(1, 30), 0);
+    assert_eq!(to_pts(2, 30), 1);
+}
+
+#[test]
+fn test_as_audacity_labels() {
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(0, 150, " Hello"),
+            utterance(6005, 6100, " tab\there"),
+        ],
+        ..Transcript::default()
+    };
+    assert_eq!(
+        transcript.as_audacity_labels(),
+        "0.000000\t1.500000\tHello\n60.050000\t61.000000\ttab here\n"
+    );
+}
+
+#[test]
+fn test_merge_by_confidence() {
+    let word = |start, stop, probability| Utterance {
+        probability: Some(probability),
+        ..utterance(start, stop, " word")
+    };
+    let fast = Transcript {
+        utterances: vec![
+            utterance(0, 100, " Fast one"),
+            utterance(100, 200, " Fast two"),
+        ],
+        word_utterances: Some(vec![word(0, 100, 0.9), word(100, 200, 0.2)]),
+        ..Transcript::default()
+    };
+    let accurate = Transcript {
+        utterances: vec![
+            utterance(0, 90, " Slow one"),
+            utterance(90, 210, " Slow two"),
+        ],
+        word_utterances: Some(vec![word(0, 90, 0.8), word(90, 210, 0.7)]),
+        ..Transcript::default()
+    };
+    let merged = Transcript::merge_by_confidence(vec![fast, accurate]);
+    assert_eq!(merged.join_with(" "), "Fast one Slow two");
+    assert_eq!(merged.word_utterances.unwrap().len(), 2);
+}
+
+#[test]
+fn test_subtitle_round_trip() {
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(0, 150, " Hello"),
+            utterance(150, 360_050, " world,\nagain"),
+        ],
+        ..Transcript::default()
+    };
+    for parsed in [
+        Transcript::from_srt(&transcript.as_srt()).unwrap(),
+        Transcript::from_vtt(&transcript.as_vtt()).unwrap(),
+    ] {
+        let cues = parsed
+            .utterances
+            .iter()
+            .map(|utterance| (utterance.start, utterance.stop, utterance.text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cues,
+            vec![(0, 150, "Hello"), (150, 360_050, "world,\nagain")]
+        );
+    }
+}
+
+#[test]
+fn test_from_vtt_errors() {
+    assert!(matches!(
+        Transcript::from_vtt("00:01.000 --> 00:02.000\nHi"),
+        Err(ModelError::SubtitleParseError(1))
+    ));
+    assert!(matches!(
+        Transcript::from_vtt("WEBVTT\n\n00:01.000 --> 00:61.000\nHi"),
+        Err(ModelError::SubtitleParseError(3))
+    ));
+    let transcript = Transcript::from_vtt(
+        "WEBVTT\n\nNOTE a comment\n\nintro\n00:01.000 --> 00:02.500 align:start\nHi\n",
+    )
+    .unwrap();
+    assert_eq!(transcript.utterances[0].text, "Hi");
+    assert_eq!(transcript.utterances[0].stop, 250);
+}
+
+#[test]
+fn test_is_empty() {
+    let mut transcript = Transcript::default();
+    assert!(transcript.is_empty());
+    transcript.utterances = vec![
+        utterance(0, 100, " "),
+        utterance(100, 200, " [BLANK_AUDIO]"),
+    ];
+    assert!(transcript.is_empty());
+    transcript.utterances.push(utterance(200, 300, " Hi"));
+    assert!(!transcript.is_empty());
+}
+
+#[test]
+fn test_text_offsets() {
+    let transcript = Transcript {
+        utterances: vec![utterance(0, 100, " Héllo "), utterance(100, 200, " world")],
+        ..Transcript::default()
+    };
+    let text = transcript.as_text();
+    let offsets = transcript.text_offsets();
+    assert_eq!(&text[offsets[0].0.clone()], "Héllo");
+    assert_eq!(&text[offsets[1].0.clone()], "world");
+    assert_eq!(offsets[1].1.start, 100);
+}
+
+#[test]
+fn test_as_html() {
+    let transcript = Transcript {
+        word_utterances: Some(vec![
+            Utterance {
+                probability: Some(0.9),
+                ..utterance(0, 10, " Fish")
+            },
+            Utterance {
+                probability: Some(0.3),
+                ..utterance(10, 20, " & chips")
+            },
+        ]),
+        ..Transcript::default()
+    };
+    let html = transcript.as_html();
+    assert!(html.contains("<span class=\"mutter-confidence-high\""));
+    assert!(html.contains("<span class=\"mutter-confidence-low\""));
+    assert!(html.contains("&amp; chips"));
+}
+
+#[test]
+fn test_as_word_srt() {
+    let mut transcript = Transcript::default();
+    assert!(matches!(
+        transcript.as_word_srt(),
+        Err(ModelError::WordTimestampsUnavailable)
+    ));
+    transcript.word_utterances = Some(vec![
+        utterance(0, 50, "[_BEG_]"),
+        utterance(0, 50, " Hel"),
+        utterance(50, 80, "lo"),
+        utterance(80, 90, ","),
+        utterance(100, 150, " world"),
+    ]);
+    assert_eq!(
+        transcript.as_word_srt().unwrap(),
+        "1\n00:00:00,000 --> 00:00:00,900\nHello,\n\
+         2\n00:00:01,000 --> 00:00:01,500\nworld\n"
+    );
+}
+
+#[test]
+fn test_as_timecoded() {
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(150, 300, " Hello"),
+            utterance(372_099, 372_500, " world "),
+        ],
+        ..Transcript::default()
+    };
+    assert_eq!(
+        transcript.as_timecoded("[", "]"),
+        "[00:00:01] Hello\n[01:02:00] world\n"
+    );
+    assert_eq!(
+        transcript.as_timecoded("", " -"),
+        "00:00:01 - Hello\n01:02:00 - world\n"
+    );
+}
+
+#[test]
+fn test_raw_output() {
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(0, 150, " Hello"),
+            utterance(150, 300, " a --> b "),
+        ],
+        ..Transcript::default()
+    };
+    assert_eq!(transcript.as_raw_text(), " Hello\n a --> b \n");
+    assert_eq!(
+        transcript.as_raw_srt(),
+        "1\n00:00:00,000 --> 00:00:01,500\n Hello\n\n\
+         2\n00:00:01,500 --> 00:00:03,000\n a --> b \n\n"
+    );
+}
+
+#[test]
+fn test_srt_writer_matches_as_srt() {
+    let transcript = Transcript {
+        utterances: vec![utterance(0, 150, " Hello"), utterance(150, 300, " world")],
+        ..Transcript::default()
+    };
+    let mut writer = SrtWriter::new(Vec::new());
+    for utterance in &transcript.utterances {
+        writer.write_utterance(utterance).unwrap();
+    }
+    let written = String::from_utf8(writer.into_inner()).unwrap();
+    assert_eq!(written, transcript.as_srt());
+}
+
+#[test]
+fn test_jsonl_writer_matches_as_jsonl() {
+    let transcript = Transcript {
+        utterances: vec![utterance(0, 150, " Hello"), utterance(150, 300, " world")],
+        ..Transcript::default()
+    };
+    let mut writer = JsonlWriter::new(Vec::new());
+    for utterance in &transcript.utterances {
+        writer.write_utterance(utterance).unwrap();
+    }
+    let written = String::from_utf8(writer.into_inner()).unwrap();
+    assert_eq!(written, transcript.as_jsonl().unwrap());
+}
+
+#[test]
+fn test_as_word_timed_json() {
+    let mut transcript = Transcript::default();
+    assert!(matches!(
+        transcript.as_word_timed_json(),
+        Err(ModelError::WordTimestampsUnavailable)
+    ));
+
+    transcript.word_utterances = Some(vec![Utterance {
+        probability: Some(1.0),
+        ..utterance(12, 34, " Hi")
+    }]);
+    assert_eq!(
+        transcript.as_word_timed_json().unwrap(),
+        r#"[{"word":"Hi","start_ms":120,"end_ms":340}]"#
+    );
+}
+
+#[test]
+fn test_speech_and_silence() {
+    let transcript = Transcript {
+        utterances: vec![utterance(100, 300, " One"), utterance(800, 1000, " Two")],
+        audio_duration: Some(Duration::from_secs(10)),
+        ..Transcript::default()
+    };
+    assert_eq!(transcript.total_speech_duration(), Duration::from_secs(4));
+    assert_eq!(transcript.silence_ratio(), Some(0.6));
+    assert_eq!(
+        transcript.silence_gaps(Duration::from_secs(1)),
+        vec![
+            Duration::ZERO..Duration::from_secs(1),
+            Duration::from_secs(3)..Duration::from_secs(8)
+        ]
+    );
+}
+
+#[test]
+fn test_segment_by_pauses() {
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(0, 100, " How are"),
+            utterance(120, 200, " you?"),
+            utterance(400, 500, " Fine."),
+            utterance(900, 1000, " Good."),
+        ],
+        ..Transcript::default()
+    };
+    let turns = transcript.segment_by_pauses(Duration::from_secs(1));
+    assert_eq!(turns.len(), 3);
+    assert_eq!(turns[0].text(), "How are you?");
+    assert_eq!((turns[0].start, turns[0].stop), (0, 200));
+    assert_eq!(
+        turns.iter().map(|turn| turn.speaker).collect::<Vec<_>>(),
+        vec![0, 1, 0]
+    );
+}
+
+#[test]
+fn test_chapters() {
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(200, 400, " Welcome to the show. Today, we talk"),
+            utterance(420, 600, " about Rust."),
+            utterance(900, 1000, " Quick aside."),
+            utterance(1300, 1500, " First, v1.2 is out! Let's dig in."),
+            utterance(6000, 6200, " Thanks for listening"),
+        ],
+        ..Transcript::default()
+    };
+    let chapters = |min_chapter_len| {
+        transcript
+            .chapters(Duration::from_secs(2), Duration::from_secs(min_chapter_len))
+            .into_iter()
+            .map(|(start, title)| (start.as_secs(), title))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        chapters(15),
+        vec![
+            (0, "Welcome to the show.".to_owned()),
+            (60, "Thanks for listening".to_owned()),
+        ]
+    );
+    assert_eq!(
+        chapters(0),
+        vec![
+            (0, "Welcome to the show.".to_owned()),
+            (9, "Quick aside.".to_owned()),
+            (13, "First, v1.2 is out!".to_owned()),
+            (60, "Thanks for listening".to_owned()),
+        ]
+    );
+    assert!(Transcript::default()
+        .chapters(Duration::from_secs(2), Duration::ZERO)
+        .is_empty());
+}
+
+#[test]
+fn test_join_with() {
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(0, 100, " Hello,"),
+            utterance(100, 150, " "),
+            utterance(150, 300, " world. "),
+        ],
+        ..Transcript::default()
+    };
+    assert_eq!(transcript.join_with(" "), "Hello, world.");
+    assert_eq!(transcript.join_with(" | "), "Hello, | world.");
+}
+
+#[test]
+fn test_remove_hallucinations() {
+    let repeated = " Thank you.".repeat(20);
+    let mut transcript = Transcript {
+        utterances: vec![
+            Utterance {
+                compression_ratio: compression_ratio(" And so my fellow Americans"),
+                ..utterance(0, 100, " And so my fellow Americans")
+            },
+            Utterance {
+                compression_ratio: compression_ratio(&repeated),
+                ..utterance(100, 200, &repeated)
+            },
+        ],
+        word_utterances: Some(vec![
+            utterance(0, 50, " And"),
+            utterance(120, 130, " Thank"),
+        ]),
+        ..Transcript::default()
+    };
+    transcript.remove_hallucinations(2.4);
+    assert_eq!(transcript.utterances.len(), 1);
+    assert_eq!(transcript.word_utterances.unwrap().len(), 1);
+}
+
+#[test]
+fn test_as_jsonl() {
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(0, 150, " Hello"),
+            utterance(150, 300, " \"world\""),
+        ],
+        ..Transcript::default()
+    };
+    assert_eq!(
+        transcript.as_jsonl().unwrap(),
+        concat!(
+            r#"{"start_ms":0,"end_ms":1500,"text":"Hello"}"#,
+            "\n",
+            r#"{"start_ms":1500,"end_ms":3000,"text":"\"world\""}"#,
+            "\n"
+        )
+    );
+
+    let transcript = Transcript {
+        utterances: vec![Utterance {
+            seek: Some(3000),
+            ..utterance(3100, 3300, " Again")
+        }],
+        ..Transcript::default()
+    };
+    assert_eq!(
+        transcript.as_jsonl().unwrap(),
+        "{\"start_ms\":31000,\"end_ms\":33000,\"text\":\"Again\",\"seek\":3000}\n"
+    );
+}
+
+#[test]
+fn test_window_start() {
+    assert_eq!(window_start(0), 0);
+    assert_eq!(window_start(2999), 0);
+    assert_eq!(window_start(3000), 3000);
+    assert_eq!(window_start(7250), 6000);
+
+    let mut transcript = Transcript {
+        utterances: vec![Utterance {
+            seek: Some(window_start(2900)),
+            ..utterance(2900, 3100, " Hello")
+        }],
+        ..Transcript::default()
+    };
+    transcript.offset_by(4500);
+    assert_eq!(transcript.utterances[0].seek, Some(6000));
+    transcript.offset_by(-4500);
+    assert_eq!(transcript.utterances[0].seek, Some(0));
+}
+
+#[test]
+fn test_speech_ranges_follow_timestamps() {
+    let secs = Duration::from_secs;
+    let mut transcript = Transcript {
+        speech_ranges: Some(vec![(secs(1), secs(3)), (secs(5), secs(8))]),
+        ..Transcript::default()
+    };
+    transcript.append(Transcript {
+        speech_ranges: Some(vec![(secs(10), secs(12))]),
+        ..Transcript::default()
+    });
+    transcript.offset_by(200);
+    assert_eq!(
+        transcript.speech_ranges,
+        Some(vec![
+            (secs(3), secs(5)),
+            (secs(7), secs(10)),
+            (secs(12), secs(14))
+        ])
+    );
+    let slice = transcript.slice(secs(4)..secs(8), true);
+    assert_eq!(
+        slice.speech_ranges,
+        Some(vec![(secs(0), secs(1)), (secs(3), secs(4))])
+    );
+}
+
+#[test]
+fn test_apply_replacements() {
+    let mut transcript = Transcript {
+        utterances: vec![utterance(0, 100, " Cat scan of the cat's catalog, cat.")],
+        word_utterances: Some(vec![utterance(0, 50, " Cat")]),
+        ..Transcript::default()
+    };
+    let replacements = HashMap::from([
+        ("cat".to_string(), "dog".to_string()),
+        ("cat scan".to_string(), "CT scan".to_string()),
+    ]);
+    transcript.apply_replacements(&replacements);
+    assert_eq!(
+        transcript.utterances[0].text,
+        " CT scan of the dog's catalog, dog."
+    );
+    assert_eq!(transcript.word_utterances.unwrap()[0].text, " Dog");
+}
+
+#[test]
+fn test_words_in_buckets() {
+    let mut transcript = Transcript::default();
+    assert!(matches!(
+        transcript.words_in_buckets(Duration::from_secs(1)),
+        Err(ModelError::WordTimestampsUnavailable)
+    ));
+
+    transcript.word_utterances = Some(vec![
+        utterance(0, 40, " And"),
+        utterance(40, 100, " so,"),
+        utterance(90, 130, " my"),
+        utterance(130, 130, "[_TT_130]"),
+        utterance(310, 350, " fellow"),
+    ]);
+    let texts = |buckets: Vec<(Duration, Vec<&Utterance>)>| {
+        buckets
+            .into_iter()
+            .map(|(start, words)| {
+                let words: Vec<&str> = words.iter().map(|word| word.text.as_str()).collect();
+                (start.as_secs(), words.concat())
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        texts(transcript.words_in_buckets(Duration::from_secs(1)).unwrap()),
+        [
+            (0, " And so, my".to_owned()),
+            (1, " my".to_owned()),
+            (2, String::new()),
+            (3, " fellow".to_owned()),
+        ]
+    );
+
+    transcript.audio_duration = Some(Duration::from_millis(5500));
+    assert_eq!(
+        texts(transcript.words_in_buckets(Duration::from_secs(2)).unwrap()),
+        [
+            (0, " And so, my".to_owned()),
+            (2, " fellow".to_owned()),
+            (4, String::new()),
+        ]
+    );
+}
+
+#[test]
+fn test_benchmark_estimate_duration() {
+    let benchmark = Benchmark {
+        inference: Duration::from_secs(3),
+        segments: 2,
+        audio_duration: Duration::from_secs(30),
+    };
+    assert_eq!(
+        benchmark.estimate_duration(Duration::from_secs(10 * 60)),
+        Some(Duration::from_secs(60))
+    );
+    let empty = Benchmark {
+        audio_duration: Duration::ZERO,
+        ..benchmark
+    };
+    assert_eq!(empty.estimate_duration(Duration::from_secs(10 * 60)), None);
+}
+
+#[test]
+fn test_merge_channels() {
+    let host = Transcript {
+        utterances: vec![
+            utterance(0, 200, " Welcome to the show."),
+            utterance(500, 600, " Tell us more."),
+            utterance(600, 700, " Please."),
+        ],
+        audio_duration: Some(Duration::from_secs(10)),
+        ..Transcript::default()
+    };
+    let guest = Transcript {
+        utterances: vec![utterance(250, 450, " Thanks for having me.")],
+        word_utterances: Some(vec![utterance(250, 300, " Thanks")]),
+        audio_duration: Some(Duration::from_secs(9)),
+        ..Transcript::default()
+    };
+    let other = Transcript {
+        utterances: vec![utterance(800, 900, " Hi!")],
+        ..Transcript::default()
+    };
+
+    let merged = Transcript::merge_channels(vec![host, guest, other], &["Host", "Guest"]);
+    assert_eq!(merged.audio_duration, Some(Duration::from_secs(10)));
+    assert_eq!(
+        merged.word_utterances.unwrap()[0].speaker.as_deref(),
+        Some("Guest")
+    );
+    let transcript = Transcript::merge_channels(
+        vec![
+            Transcript {
+                utterances: vec![utterance(0, 100, " Morning.")],
+                ..Transcript::default()
+            },
+            Transcript::default(),
+        ],
+        &[],
+    );
+    assert_eq!(transcript.as_text_with_speakers(), "Channel 1: Morning.\n");
+    let merged = Transcript {
+        utterances: merged.utterances,
+        ..Transcript::default()
+    };
+    assert_eq!(
+        merged.as_text_with_speakers(),
+        "Host: Welcome to the show.\n\
+         Guest: Thanks for having me.\n\
+         Host: Tell us more. Please.\n\
+         Channel 3: Hi!\n"
+    );
+    assert_eq!(
+        Transcript {
+            utterances: vec![utterance(0, 100, " Hello"), utterance(100, 200, " there")],
+            ..Transcript::default()
+        }
+        .as_text_with_speakers(),
+        "Hello\nthere\n"
+    );
+}
+
+#[test]
+fn test_confidence_stats() {
+    let mut transcript = Transcript::default();
+    assert_eq!(transcript.confidence_stats(), None);
+
+    transcript.utterances = (1..=10)
+        .map(|tenths: u8| Utterance {
+            avg_logprob: Some((f32::from(tenths) / 10.0).ln()),
+            ..utterance(0, 100, " Hello")
+        })
+        .rev()
+        .collect();
+    transcript.utterances.push(utterance(0, 100, " Parsed"));
+    let stats = transcript.confidence_stats().unwrap();
+    assert_eq!(stats.segments, 10);
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+    assert!(close(stats.min, 0.1));
+    assert!(close(stats.max, 1.0));
+    assert!(close(stats.mean, 0.55));
+    assert!(close(stats.p10, 0.1));
+    assert!(close(stats.p50, 0.5));
+    assert!(close(stats.p90, 0.9));
+}
+
+#[test]
+fn test_write_all() {
+    let dir = std::env::temp_dir().join("mutter-test-write-all");
+    let _ = fs::remove_dir_all(&dir);
+    let transcript = Transcript {
+        utterances: vec![utterance(0, 150, " Hello"), utterance(150, 300, " world")],
+        ..Transcript::default()
+    };
+    transcript.write_all(&dir, "greeting").unwrap();
+
+    let read = |extension: &str| fs::read_to_string(dir.join(format!("greeting.{extension}")));
+    assert_eq!(read("txt").unwrap(), transcript.as_text());
+    assert_eq!(read("srt").unwrap(), transcript.as_srt());
+    assert_eq!(read("vtt").unwrap(), transcript.as_vtt());
+    let json: Transcript = serde_json::from_str(&read("json").unwrap()).unwrap();
+    assert_eq!(json.as_srt(), transcript.as_srt());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_slice() {
+    let transcript = Transcript {
+        utterances: vec![
+            utterance(0, 300, " And so,"),
+            utterance(300, 600, " my fellow Americans,"),
+            utterance(600, 900, " ask not."),
+        ],
+        word_utterances: Some(vec![
+            utterance(0, 300, " And so,"),
+            utterance(300, 350, " my"),
+            utterance(350, 450, " fellow"),
+            utterance(450, 600, " Americans,"),
+            utterance(600, 900, " ask not."),
+        ]),
+        audio_duration: Some(Duration::from_secs(10)),
+        ..Transcript::default()
+    };
+    let texts = |utterances: &[Utterance]| {
+        utterances
+            .iter()
+            .map(|utterance| utterance.text.trim().to_owned())
+            .collect::<Vec<_>>()
+    };
+
+    let slice = transcript.slice(Duration::from_secs(4)..Duration::from_secs(6), false);
+    assert_eq!(texts(&slice.utterances), ["my fellow Americans,"]);
+    assert_eq!(
+        texts(slice.word_utterances.as_ref().unwrap()),
+        ["my", "fellow", "Americans,"]
+    );
+    assert_eq!(slice.utterances[0].start, 300);
+    assert_eq!(slice.audio_duration, Some(Duration::from_secs(10)));
+
+    let slice = transcript.slice(Duration::from_secs(5)..Duration::from_secs(8), true);
+    assert_eq!(
+        texts(&slice.utterances),
+        ["my fellow Americans,", "ask not."]
+    );
+    assert_eq!(slice.utterances[0].start, 0);
+    assert_eq!(slice.utterances[0].stop, 100);
+    assert_eq!(slice.utterances[1].start, 100);
+    assert_eq!(slice.word_utterances.unwrap()[0].start, 0);
+    assert_eq!(slice.audio_duration, Some(Duration::from_secs(3)));
+
+    assert!(transcript
+        .slice(Duration::from_secs(9)..Duration::from_secs(12), true)
+        .is_empty());
+}
+
+#[test]
+fn test_as_srt_with() {
+    let transcript = Transcript {
+        utterances: vec![utterance(0, 150, " Hello"), utterance(150, 300, " world ")],
+        ..Transcript::default()
+    };
+    assert_eq!(
+        transcript.as_srt_with(LineEnding::Lf, false),
+        "1\n00:00:00,000 --> 00:00:01,500\nHello\n\n\
+         2\n00:00:01,500 --> 00:00:03,000\nworld\n\n"
+    );
+    assert_eq!(
+        transcript.as_srt_with(LineEnding::CrLf, true),
+        "\u{feff}1\r\n00:00:00,000 --> 00:00:01,500\r\nHello\r\n\r\n\
+         2\r\n00:00:01,500 --> 00:00:03,000\r\nworld\r\n\r\n"
+    );
+    assert_eq!(
+        Transcript::default().as_srt_with(LineEnding::CrLf, false),
+        ""
+    );
+    let parsed = Transcript::from_srt(&transcript.as_srt_with(LineEnding::CrLf, true)).unwrap();
+    assert_eq!(parsed.as_srt(), transcript.as_srt());
+}
+
+#[test]
+fn test_token_assembler() {
+    let mut tokens = TokenAssembler::default();
+    let word = tokens.push(b" Hello", 0, 10, 0.9).unwrap();
+    assert_eq!(
+        (word.text.as_str(), word.start, word.stop),
+        (" Hello", 0, 10)
+    );
+
+    // "日" is E6 97 A5, split across two tokens.
+    assert!(tokens.push(b" \xe6\x97", 10, 20, 0.8).is_none());
+    let word = tokens.push(b"\xa5", 20, 30, 0.7).unwrap();
+    assert_eq!(word.text, " 日");
+    assert_eq!(
+        (word.start, word.stop, word.probability),
+        (10, 30, Some(0.7))
+    );
+
+    // Bytes that can't start a character are replaced right away.
+    let word = tokens.push(b"\xff", 30, 40, 0.5).unwrap();
+    assert_eq!(word.text, "\u{fffd}");
+    assert!(tokens.push(b"\xe6", 40, 50, 0.5).is_none());
+    assert_eq!(tokens.finish().unwrap().text, "\u{fffd}");
+    assert!(tokens.finish().is_none());
+}
+
+#[test]
+fn test_snap_to_fps() {
+    assert_eq!(snap_millis(101, None), 1010);
+    // 1.01 s is frame 25.25 at 25 fps, and 1.04 s frame 31.2 at 30 fps.
+    assert_eq!(snap_millis(101, Some(25.0)), 1000);
+    assert_eq!(snap_millis(102, Some(25.0)), 1040);
+    assert_eq!(snap_millis(104, Some(30.0)), 1033);
+    assert_eq!(snap_millis(105, Some(0.0)), 1050);
+
+    let transcript = Transcript {
+        utterances: vec![utterance(101, 255, " Hello")],
+        ..Transcript::default()
+    };
+    assert_eq!(
+        transcript.as_srt_at_fps(24.0),
+        "1\n00:00:01,000 --> 00:00:02,542\nHello\n"
+    );
+    assert_eq!(
+        transcript.as_vtt_at_fps(24.0),
+        "WEBVTT\n00:00:01.000 --> 00:00:02.542\nHello\n\n"
+    );
+    assert_eq!(transcript.as_srt_at_fps(1000.0), transcript.as_srt());
+}
+
+#[test]
+fn test_source_format_frame_at() {
+    let format = SourceFormat {
+        sample_rate: 44100,
+        channels: 2,
+    };
+    assert_eq!(format.frame_at(0), 0);
+    assert_eq!(format.frame_at(150), 66150);
+    assert_eq!(format.frame_at(-10), 0);
+}
+
+#[test]
+fn test_sort_by_time() {
+    let mut transcript = Transcript {
+        utterances: vec![
+            utterance(500, 700, " Later"),
+            utterance(0, 200, " First"),
+            utterance(0, 100, " Shorter"),
+        ],
+        word_utterances: Some(vec![utterance(50, 60, " b"), utterance(10, 20, " a")]),
+        ..Transcript::default()
+    };
+    let srt = transcript.as_srt();
+    assert!(srt.starts_with("1\n00:00:00,000 --> 00:00:01,000\nShorter\n"));
+    assert!(srt.contains("3\n00:00:05,000 --> 00:00:07,000\nLater\n"));
+    let text = transcript.as_text();
+    assert_eq!(text, "Shorter\nFirst\nLater\n");
+    assert_eq!(transcript.as_text_with_speakers(), text);
+    assert!(transcript.as_html().contains(">a</span> <span"));
+
+    transcript.sort_by_time();
+    assert_eq!(transcript.as_text(), text);
+    let words = transcript.word_utterances.as_ref().unwrap();
+    assert_eq!(words[0].text, " a");
+    assert_eq!(transcript.as_srt(), srt);
+}
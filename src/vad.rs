@@ -0,0 +1,296 @@
+//! Energy-based voice activity detection.
+//!
+//! This is a simple heuristic: a frame of audio is considered speech if its loudness
+//! (root mean square) is above a threshold. It works well for close-talking
+//! microphones in quiet rooms, and poorly with background noise or music.
+
+use std::time::{Duration, Instant};
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::{transcode, transcript::Transcript, Model, ModelError, TranscribeOptions};
+
+/// Energy-based voice activity detector, for 16 kHz single-channel audio.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EnergyVad {
+    /// Length of each frame of audio that is classified as speech or non-speech.
+    pub frame_length: Duration,
+    /// Root mean square amplitude, from 0 to 1, above which a frame is speech.
+    pub threshold: f32,
+}
+
+impl Default for EnergyVad {
+    fn default() -> Self {
+        Self {
+            frame_length: Duration::from_millis(30),
+            threshold: 0.01,
+        }
+    }
+}
+
+impl EnergyVad {
+    /// Whether the given frame of samples contains speech.
+    #[must_use]
+    pub fn is_speech(&self, frame: &[f32]) -> bool {
+        !frame.is_empty() && rms(frame) > self.threshold
+    }
+
+    /// Finds the end of the first utterance: the sample index at which the first
+    /// silence of at least `min_silence` after detected speech starts. `None` if
+    /// there is no speech, or speech doesn't stop for that long.
+    #[must_use]
+    pub fn endpoint(&self, samples: &[f32], min_silence: Duration) -> Option<usize> {
+        let frame_samples = transcode::duration_to_samples(self.frame_length).max(1);
+        let min_silence_samples = transcode::duration_to_samples(min_silence);
+        let mut heard_speech = false;
+        let mut silence_start = None;
+        for (idx, frame) in samples.chunks(frame_samples).enumerate() {
+            let frame_start = idx * frame_samples;
+            if self.is_speech(frame) {
+                heard_speech = true;
+                silence_start = None;
+            } else if heard_speech {
+                let start = *silence_start.get_or_insert(frame_start);
+                if frame_start + frame.len() - start >= min_silence_samples {
+                    return Some(start);
+                }
+            }
+        }
+        None
+    }
+
+    /// Cuts off the silence at the end of the audio, keeping [`TRIM_MARGIN`] of it
+    /// after the last frame of speech, as the quiet ends of words often fall below the
+    /// threshold. Audio without any speech is trimmed to nothing.
+    #[must_use]
+    pub fn trim_trailing_silence<'a>(&self, samples: &'a [f32]) -> &'a [f32] {
+        let frame_samples = transcode::duration_to_samples(self.frame_length).max(1);
+        let speech_end = samples
+            .chunks(frame_samples)
+            .rposition(|frame| self.is_speech(frame))
+            .map_or(0, |idx| {
+                (idx + 1) * frame_samples + transcode::duration_to_samples(TRIM_MARGIN)
+            });
+        &samples[..speech_end.min(samples.len())]
+    }
+
+    /// Finds the stretches of speech in the audio, as start and end times, e.g. to cut
+    /// the silence between them out of the audio. Silences shorter than `min_silence`
+    /// are counted as speech, so pauses between words don't split a stretch up.
+    #[must_use]
+    pub fn speech_ranges(
+        &self,
+        samples: &[f32],
+        min_silence: Duration,
+    ) -> Vec<(Duration, Duration)> {
+        to_durations(self.speech_sample_ranges(samples, min_silence))
+    }
+
+    /// Does [`EnergyVad::trim_trailing_silence`] and [`EnergyVad::speech_ranges`],
+    /// with [`TRIM_MARGIN`] as the shortest silence, in a single pass over the audio.
+    pub(crate) fn trim_and_find_speech<'a>(
+        &self,
+        samples: &'a [f32],
+    ) -> (&'a [f32], Vec<(Duration, Duration)>) {
+        let ranges = self.speech_sample_ranges(samples, TRIM_MARGIN);
+        let speech_end = ranges.last().map_or(0, |&(_, end)| {
+            end + transcode::duration_to_samples(TRIM_MARGIN)
+        });
+        (
+            &samples[..speech_end.min(samples.len())],
+            to_durations(ranges),
+        )
+    }
+
+    /// [`EnergyVad::speech_ranges`], in samples.
+    fn speech_sample_ranges(&self, samples: &[f32], min_silence: Duration) -> Vec<(usize, usize)> {
+        let frame_samples = transcode::duration_to_samples(self.frame_length).max(1);
+        // Adjacent frames of speech are always one stretch.
+        let min_silence_samples = transcode::duration_to_samples(min_silence).max(1);
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for (idx, frame) in samples.chunks(frame_samples).enumerate() {
+            if !self.is_speech(frame) {
+                continue;
+            }
+            let (start, end) = (idx * frame_samples, idx * frame_samples + frame.len());
+            match ranges.last_mut() {
+                Some((_, last_end)) if start - *last_end < min_silence_samples => *last_end = end,
+                _ => ranges.push((start, end)),
+            }
+        }
+        ranges
+    }
+}
+
+/// Stretches of speech, as the times they start and end at.
+pub(crate) type SpeechRanges = Vec<(Duration, Duration)>;
+
+/// Converts stretches of speech from samples to times.
+fn to_durations(ranges: Vec<(usize, usize)>) -> SpeechRanges {
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            (
+                transcode::samples_to_duration(start),
+                transcode::samples_to_duration(end),
+            )
+        })
+        .collect()
+}
+
+/// Silence kept after the last speech by [`EnergyVad::trim_trailing_silence`].
+pub const TRIM_MARGIN: Duration = Duration::from_millis(200);
+
+/// Root mean square amplitude of a frame of samples.
+fn rms(frame: &[f32]) -> f32 {
+    if frame.is_empty() {
+        return 0.0;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mean_square = frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len() as f32;
+    mean_square.sqrt()
+}
+
+/// Sample indices at which to split audio into pieces of at most `piece_length`, each
+/// cut at the quietest frame in the last few seconds before the limit, so words are
+/// unlikely to be cut in half. Empty if the audio fits in one piece.
+pub(crate) fn split_points(samples: &[f32], piece_length: Duration) -> Vec<usize> {
+    let piece_samples = transcode::duration_to_samples(piece_length).max(1);
+    let search_samples =
+        (piece_samples / 4).min(transcode::duration_to_samples(Duration::from_secs(5)));
+    let frame_samples = transcode::duration_to_samples(EnergyVad::default().frame_length);
+    let mut points = Vec::new();
+    let mut start = 0;
+    while samples.len() - start > piece_samples {
+        let end = start + piece_samples;
+        let search_start = end - search_samples;
+        let quietest = samples[search_start..end]
+            .chunks(frame_samples)
+            .enumerate()
+            .min_by(|(_, a), (_, b)| rms(a).total_cmp(&rms(b)))
+            .map_or(0, |(idx, _)| idx * frame_samples);
+        start = (search_start + quietest).max(start + 1);
+        points.push(start);
+    }
+    points
+}
+
+impl Model {
+    /// Transcribes audio up to the end of the first utterance, given the audio is a
+    /// byte array of a file. Anything after the first silence of at least
+    /// `min_silence` following speech, as detected by `vad`, is ignored. Useful for
+    /// push-to-talk and other turn-based voice interfaces.
+    ///
+    /// # Arguments
+    /// - `audio`: Audio to transcribe. An array of bytes.
+    /// - `vad`: [`EnergyVad`] used to detect speech.
+    /// - `min_silence`: Length of silence that ends the utterance.
+    /// - `options`: [`TranscribeOptions`] to transcribe with.
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [`Transcript`]
+    pub fn transcribe_until_silence(
+        &self,
+        audio: impl AsRef<[u8]>,
+        vad: &EnergyVad,
+        min_silence: Duration,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        trace!("Decoding audio.");
+        let decode_start = Instant::now();
+        let samples = transcode::decode(audio.as_ref().to_vec())?.samples;
+        let decode_time = decode_start.elapsed();
+        let mut transcript =
+            self.transcribe_pcm_until_silence(&samples, vad, min_silence, options)?;
+        transcript.timings.decode = decode_time;
+        Ok(transcript)
+    }
+
+    /// Transcribes audio up to the end of the first utterance, given the audio is an
+    /// [f32] float array of codec `pcm_s16le` and in single-channel format.
+    /// See [`Model::transcribe_until_silence`].
+    ///
+    /// # Errors
+    /// - [`ModelError`]
+    /// # Returns
+    /// [`Transcript`]
+    pub fn transcribe_pcm_until_silence(
+        &self,
+        audio: &[f32],
+        vad: &EnergyVad,
+        min_silence: Duration,
+        options: &TranscribeOptions,
+    ) -> Result<Transcript, ModelError> {
+        let end = vad.endpoint(audio, min_silence).unwrap_or(audio.len());
+        trace!("Endpoint detected at sample {end} of {}", audio.len());
+        self.transcribe_pcm(&audio[..end], options)
+    }
+}
+
+#[test]
+fn test_split_points() {
+    let mut samples = vec![0.5; 16000 * 25];
+    // A quiet moment two seconds before the 10 second limit.
+    samples[16000 * 8..16000 * 8 + 800].fill(0.0);
+    let points = split_points(&samples, Duration::from_secs(10));
+    assert_eq!(points.len(), 2);
+    assert!((16000 * 8..16000 * 8 + 800).contains(&points[0]));
+    assert!(points
+        .windows(2)
+        .all(|pair| pair[1] - pair[0] <= 16000 * 10));
+    assert!(split_points(&samples[..16000], Duration::from_secs(10)).is_empty());
+}
+
+#[test]
+fn test_endpoint() {
+    let vad = EnergyVad::default();
+    let mut samples = vec![0.0; 8000];
+    samples.resize(24000, 0.5);
+    samples.resize(40000, 0.0);
+    samples.resize(56000, 0.5);
+
+    let end = vad.endpoint(&samples, Duration::from_millis(500)).unwrap();
+    assert!((24000..24500).contains(&end));
+    assert_eq!(vad.endpoint(&samples, Duration::from_secs(2)), None);
+    assert_eq!(vad.endpoint(&vec![0.0; 16000], Duration::ZERO), None);
+}
+
+#[test]
+fn test_speech_ranges() {
+    let vad = EnergyVad::default();
+    // Speech for 0.96 s, a 0.12 s pause, 0.48 s of speech, then
+    // 0.99 s of silence and 0.48 s of speech, in 30 ms frames of 480 samples.
+    let mut samples = vec![0.5; 15360];
+    samples.resize(17280, 0.0);
+    samples.resize(24960, 0.5);
+    samples.resize(40800, 0.0);
+    samples.resize(48480, 0.5);
+    let ms = Duration::from_millis;
+    assert_eq!(
+        vad.speech_ranges(&samples, ms(200)),
+        vec![(ms(0), ms(1560)), (ms(2550), ms(3030))]
+    );
+    assert_eq!(
+        vad.speech_ranges(&samples, Duration::ZERO),
+        vec![(ms(0), ms(960)), (ms(1080), ms(1560)), (ms(2550), ms(3030))]
+    );
+    assert!(vad.speech_ranges(&vec![0.0; 16000], ms(200)).is_empty());
+}
+
+#[test]
+fn test_trim_trailing_silence() {
+    let vad = EnergyVad::default();
+    let mut samples = vec![0.5; 16000];
+    samples.resize(48000, 0.0);
+    // Trimmed at a frame boundary, 30 ms frames of 480 samples.
+    let trimmed = vad.trim_trailing_silence(&samples).len();
+    assert!((16000 + 3200..16000 + 3200 + 480).contains(&trimmed));
+    assert_eq!(vad.trim_trailing_silence(&samples[..16000]).len(), 16000);
+    assert!(vad.trim_trailing_silence(&samples[16000..]).is_empty());
+
+    let (trimmed_once, ranges) = vad.trim_and_find_speech(&samples);
+    assert_eq!(trimmed_once.len(), trimmed);
+    assert_eq!(ranges, vad.speech_ranges(&samples, TRIM_MARGIN));
+}